@@ -1,3 +1,6 @@
 fn main() {
-    embuild::espidf::sysenv::output();
+    // the esp-idf environment is only set up for the esp, not for the host tests
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("espidf") {
+        embuild::espidf::sysenv::output();
+    }
 }
@@ -3,17 +3,18 @@ extern crate alloc;
 use alloc::sync::Arc;
 
 use super::{
-    BleKeyboardMaster, KeyboardKeyReport, MouseKeyReport, HID_REPORT_DISCRIPTOR, KEYBOARD_ID,
-    MEDIA_KEYS_ID, MOUSE_ID,
+    BleKeyboardMaster, KeyboardKeyReport, MediaKeyReport, MouseKeyReport, NkroKeyReport,
+    ProtocolMode, SystemControlReport, ABSOLUTE_MOUSE_ID, HID_REPORT_DISCRIPTOR, KEYBOARD_ID,
+    MEDIA_KEYS_ID, MOUSE_ID, NKRO_KEYBOARD_ID, NKRO_KEYS_BYTES, SYSTEM_CONTROL_ID,
 };
-use crate::ble::BleStatus;
-use crate::config::enums::Kc;
+use crate::ble::{BleStatus, HostLedState};
 use crate::config::layout::Layout;
 use crate::config::user_config::master::ESP_POWER_LEVEL;
-use crate::config::user_config::{BLE_SLAVE_UUID, KB_NAME};
+use crate::config::user_config::{BLE_HOST_LED_UUID, BLE_SLAVE_UUID, KB_NAME};
 use crate::delay::*;
-use crate::key_provision::key_provision;
-use crate::matrix::RegisteredMatrixKeys;
+use crate::key_provision::{key_provision, macros::MacroPlayer, one_shot::OneShot};
+use crate::layer::LayerState;
+use crate::matrix::{KeyPos, RegisteredMatrixKeys};
 
 use esp32_nimble::{
     enums::*, utilities::mutex::Mutex, uuid128, BLEAdvertisementData, BLEDevice, BLEHIDDevice,
@@ -58,13 +59,58 @@ impl BleKeyboardMaster {
             NimbleProperties::READ | NimbleProperties::WRITE | NimbleProperties::WRITE_NO_RSP,
         );
 
+        // the host LED state is forwarded to the slave
+        let output_slave_host_led = service.lock().create_characteristic(
+            BLE_HOST_LED_UUID,
+            NimbleProperties::READ | NimbleProperties::NOTIFY,
+        );
+
         // ------------------ HID DEVICES INIT ----------------------
         let mut hid = BLEHIDDevice::new(server);
 
         let input_keyboard = hid.input_report(KEYBOARD_ID);
+        let input_nkro_keyboard = hid.input_report(NKRO_KEYBOARD_ID);
         let output_keyboard = hid.output_report(KEYBOARD_ID);
         let input_media_keys = hid.input_report(MEDIA_KEYS_ID);
         let input_mouse = hid.input_report(MOUSE_ID);
+        let feature_mouse = hid.feature_report(MOUSE_ID);
+        let input_absolute_mouse = hid.input_report(ABSOLUTE_MOUSE_ID);
+        let input_system_control = hid.input_report(SYSTEM_CONTROL_ID);
+        let input_boot_keyboard = hid.boot_input();
+        let output_boot_keyboard = hid.boot_output();
+
+        // the hosts start in the report protocol, some of them switch to the boot protocol
+        let protocol_mode = Arc::new(Mutex::new(ProtocolMode::Report));
+        hid.protocol_mode()
+            .lock()
+            .set_value(&[ProtocolMode::Report as u8])
+            .on_write({
+                let protocol_mode = Arc::clone(&protocol_mode);
+                move |args| {
+                    if let Some(mode) = args.recv_data().first() {
+                        *protocol_mode.lock() = if *mode == ProtocolMode::Boot as u8 {
+                            ProtocolMode::Boot
+                        } else {
+                            ProtocolMode::Report
+                        };
+
+                        log::info!("Protocol mode: {:?}", *protocol_mode.lock());
+                    }
+                }
+            });
+
+        // the host enables the high resolution wheel through the resolution multiplier
+        let wheel_resolution = Arc::new(Mutex::new(0));
+        feature_mouse.lock().set_value(&[0]).on_write({
+            let wheel_resolution = Arc::clone(&wheel_resolution);
+            move |args| {
+                if let Some(resolution_multiplier) = args.recv_data().first() {
+                    *wheel_resolution.lock() = *resolution_multiplier;
+
+                    log::info!("Wheel resolution multiplier: {resolution_multiplier:#04x}");
+                }
+            }
+        });
 
         hid.manufacturer("Espressif");
         hid.pnp(0x02, 0x05ac, 0x820a, 0x0210);
@@ -95,12 +141,27 @@ impl BleKeyboardMaster {
         Self {
             server,
             input_slave,
+            output_slave_host_led,
             input_keyboard,
+            input_nkro_keyboard,
             output_keyboard,
+            input_boot_keyboard,
+            output_boot_keyboard,
             input_media_keys,
+            input_system_control,
             input_mouse,
+            feature_mouse,
+            input_absolute_mouse,
+            protocol_mode,
+            wheel_resolution,
             current_keyboard_report: KeyboardKeyReport::default(),
             previous_keyboard_report: KeyboardKeyReport::default(),
+            current_nkro_report: [0; NKRO_KEYS_BYTES + 1],
+            previous_nkro_report: [0; NKRO_KEYS_BYTES + 1],
+            current_media_report: MediaKeyReport::default(),
+            previous_media_report: MediaKeyReport::default(),
+            current_system_control_report: SystemControlReport::default(),
+            previous_system_control_report: SystemControlReport::default(),
             current_mouse_report: MouseKeyReport::default(),
             previous_mouse_report: MouseKeyReport::default(),
         }
@@ -119,12 +180,70 @@ impl BleKeyboardMaster {
             "ble_keyboard.current_keyboard_report: {:?}",
             self.current_keyboard_report
         );
-        self.input_keyboard
+
+        // the boot report has the same layout, but is sent without the report id
+        let input_keyboard = if self.is_boot_protocol() {
+            &self.input_boot_keyboard
+        } else {
+            &self.input_keyboard
+        };
+
+        input_keyboard
             .lock()
             .set_value(self.current_keyboard_report.as_bytes())
             .notify();
     }
 
+    /// Check if the host has switched to the boot protocol
+    fn is_boot_protocol(&self) -> bool {
+        *self.protocol_mode.lock() == ProtocolMode::Boot
+    }
+
+    /// Send n-key rollover keyboard report
+    async fn send_nkro_report(&mut self) {
+        // debug log
+        #[cfg(feature = "debug")]
+        log::info!(
+            "ble_keyboard.current_nkro_report: {:?}",
+            self.current_nkro_report
+        );
+
+        self.input_nkro_keyboard
+            .lock()
+            .set_value(&self.current_nkro_report)
+            .notify();
+    }
+
+    /// Send media key report
+    async fn send_media_report(&mut self) {
+        // debug log
+        #[cfg(feature = "debug")]
+        log::info!(
+            "ble_keyboard.current_media_report: {:?}",
+            self.current_media_report
+        );
+
+        self.input_media_keys
+            .lock()
+            .set_value(self.current_media_report.as_bytes())
+            .notify();
+    }
+
+    /// Send system control report
+    async fn send_system_control_report(&mut self) {
+        // debug log
+        #[cfg(feature = "debug")]
+        log::info!(
+            "ble_keyboard.current_system_control_report: {:?}",
+            self.current_system_control_report
+        );
+
+        self.input_system_control
+            .lock()
+            .set_value(self.current_system_control_report.as_bytes())
+            .notify();
+    }
+
     /// Send mouse report
     async fn send_mouse_report(&mut self) {
         // debug log
@@ -140,6 +259,21 @@ impl BleKeyboardMaster {
             .notify();
     }
 
+    /// Send absolute mouse report
+    async fn send_absolute_mouse_report(&mut self) {
+        // debug log
+        #[cfg(feature = "debug")]
+        log::info!(
+            "ble_keyboard.current_absolute_mouse_report: {:?}",
+            self.current_mouse_report.construct_absolute()
+        );
+
+        self.input_absolute_mouse
+            .lock()
+            .set_value(self.current_mouse_report.construct_absolute().as_bytes())
+            .notify();
+    }
+
     /// Set BLE Power-save mode
     fn set_ble_power_save(&mut self) {
         // set power save
@@ -169,9 +303,39 @@ impl BleKeyboardMaster {
         }
     }
 
-    /// Check if mouse report changed
+    /// Check if n-key rollover keyboard report changed
+    fn is_nkro_report_changed(&mut self) -> bool {
+        if self.previous_nkro_report != self.current_nkro_report {
+            self.previous_nkro_report = self.current_nkro_report;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Check if media key report changed
+    fn is_media_report_changed(&mut self) -> bool {
+        if self.previous_media_report != self.current_media_report {
+            self.previous_media_report = self.current_media_report;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Check if system control report changed
+    fn is_system_control_report_changed(&mut self) -> bool {
+        if self.previous_system_control_report != self.current_system_control_report {
+            self.previous_system_control_report = self.current_system_control_report;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Check if mouse buttons changed, the cursor moves are relative and sent when they are due
     fn is_mouse_report_changed(&mut self) -> bool {
-        if self.previous_mouse_report != self.current_mouse_report {
+        if self.previous_mouse_report.buttons() != self.current_mouse_report.buttons() {
             self.previous_mouse_report = self.current_mouse_report;
             true
         } else {
@@ -182,23 +346,59 @@ impl BleKeyboardMaster {
 
 pub async fn ble_tx(
     pressed_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
-    layer: &Arc<Mutex<usize>>,
+    layer: &Arc<Mutex<LayerState>>,
     ble_status: &Arc<Mutex<BleStatus>>,
+    host_led_state: &Arc<Mutex<HostLedState>>,
 ) -> ! {
     // init ble
     let mut ble_keyboard: BleKeyboardMaster = BleKeyboardMaster::new().await;
 
     // initialize layers
     let layout = Layout::init();
+    layer
+        .lock()
+        .set_conditional_layers(layout.conditional_layers);
 
     // vec to store the keys needed to be removed
-    let mut pressed_keys_to_remove: Vec<Kc, 12> = Vec::new();
+    let mut pressed_keys_to_remove: Vec<KeyPos, 12> = Vec::new();
 
     // set ble power to lowest possible
     // ble_keyboard.set_ble_power_save();
 
     let mut keyboard_key_report: KeyboardKeyReport = KeyboardKeyReport::default();
+    let mut nkro_key_report: NkroKeyReport = NkroKeyReport::default();
+    let mut media_key_report: MediaKeyReport = MediaKeyReport::default();
+    let mut system_control_report: SystemControlReport = SystemControlReport::default();
     let mut mouse_key_report: MouseKeyReport = MouseKeyReport::default();
+    let mut one_shot: OneShot = OneShot::default();
+    let mut macro_player: MacroPlayer = MacroPlayer::default();
+
+    // the slave reads the current host LED state once it has subscribed
+    ble_keyboard
+        .output_slave_host_led
+        .lock()
+        .set_value(&[host_led_state.lock().leds()]);
+
+    // on_write callback, the host writes the LED state to the output report of the used protocol
+    for output_keyboard in [
+        &ble_keyboard.output_keyboard,
+        &ble_keyboard.output_boot_keyboard,
+    ] {
+        output_keyboard.lock().on_write({
+            let host_led_state = Arc::clone(host_led_state);
+            let output_slave_host_led = Arc::clone(&ble_keyboard.output_slave_host_led);
+            move |args| {
+                if let Some(leds) = args.recv_data().first() {
+                    host_led_state.lock().set(*leds);
+                    output_slave_host_led.lock().set_value(&[*leds]).notify();
+
+                    // debug log
+                    #[cfg(feature = "debug")]
+                    log::info!("Received host LED state: {:?}", *host_led_state.lock());
+                }
+            }
+        });
+    }
 
     #[cfg(feature = "split")]
     let slave_key_report: Arc<Mutex<[u8; 6]>> = Arc::new(Mutex::new([0; 6]));
@@ -238,12 +438,33 @@ pub async fn ble_tx(
                 &layout,
                 layer,
                 &mut keyboard_key_report,
+                &mut nkro_key_report,
+                &mut media_key_report,
+                &mut system_control_report,
                 &mut mouse_key_report,
                 &mut pressed_keys_to_remove,
+                &mut one_shot,
+                &mut macro_player,
             )
             .await;
 
-            ble_keyboard.current_keyboard_report = keyboard_key_report;
+            // play the next step of the pressed macros, one report at a time
+            macro_player.play(&mut keyboard_key_report);
+
+            // only one of the keyboard reports holds the keys, the other one is released
+            // boot protocol hosts only read the 6-key report
+            if nkro_key_report.is_enabled() && !ble_keyboard.is_boot_protocol() {
+                ble_keyboard.current_keyboard_report = KeyboardKeyReport::default();
+                ble_keyboard.current_nkro_report = nkro_key_report.construct(&keyboard_key_report);
+            } else {
+                ble_keyboard.current_keyboard_report = keyboard_key_report;
+                ble_keyboard.current_nkro_report = [0; NKRO_KEYS_BYTES + 1];
+            }
+            ble_keyboard.current_media_report = media_key_report;
+            ble_keyboard.current_system_control_report = system_control_report;
+            // move the cursor and the wheel of the held mouse keys
+            mouse_key_report.set_wheel_resolution(*ble_keyboard.wheel_resolution.lock());
+            mouse_key_report.update();
             ble_keyboard.current_mouse_report = mouse_key_report;
 
             // sent the new keyboard report only if it differes from the previous
@@ -251,15 +472,41 @@ pub async fn ble_tx(
                 ble_keyboard.send_keyboard_report().await;
             }
 
-            // in case the cursor is being moved
-            if ble_keyboard
-                .current_mouse_report
-                .is_cursor_position_changed()
-                || ble_keyboard.is_mouse_report_changed()
+            // sent the new n-key rollover keyboard report only if it differes from the previous
+            if ble_keyboard.is_nkro_report_changed() {
+                ble_keyboard.send_nkro_report().await;
+            }
+
+            // sent the new media key report only if it differes from the previous
+            if !ble_keyboard.is_boot_protocol() && ble_keyboard.is_media_report_changed() {
+                ble_keyboard.send_media_report().await;
+            }
+
+            // sent the new system control report only if it differes from the previous
+            if !ble_keyboard.is_boot_protocol() && ble_keyboard.is_system_control_report_changed() {
+                ble_keyboard.send_system_control_report().await;
+            }
+
+            // the one-shot keys are cleared once the key using them has been sent
+            one_shot.report_sent(&mut keyboard_key_report, layer);
+
+            // in case the cursor is being moved, the mouse is not available in the boot protocol
+            if !ble_keyboard.is_boot_protocol()
+                && (ble_keyboard
+                    .current_mouse_report
+                    .is_cursor_position_changed()
+                    || ble_keyboard.is_mouse_report_changed())
             {
                 ble_keyboard.send_mouse_report().await;
             }
 
+            // the pointer jumps to the warped position once, the absolute mouse is not available in the boot protocol
+            if !ble_keyboard.is_boot_protocol()
+                && ble_keyboard.current_mouse_report.is_pointer_warped()
+            {
+                ble_keyboard.send_absolute_mouse_report().await;
+            }
+
             // there must be a delay so the WDT in not triggered
             delay_ms(1).await;
         } else {
@@ -2,38 +2,49 @@
 #![allow(dead_code)]
 
 extern crate alloc;
+#[cfg(target_os = "espidf")]
 use alloc::sync::Arc;
 
 use embassy_time::{Duration, Instant};
-use esp32_nimble::BLEClient;
-use esp32_nimble::{hid::*, utilities::mutex::Mutex, BLECharacteristic, BLEServer};
+#[cfg(target_os = "espidf")]
+use esp32_nimble::{hid::*, utilities::mutex::Mutex, BLECharacteristic, BLEClient, BLEServer};
 use zerocopy::{Immutable, IntoBytes};
 
-use crate::mouse::MouseKeyReport;
+use crate::config::{enums::NkroAction, user_config::NKRO_ENABLED};
 
-#[cfg(feature = "master")]
+#[cfg(target_os = "espidf")]
+use crate::{config::user_config::MOUSEKEY_WHEEL_RESOLUTION, mouse::MouseKeyReport};
+
+#[cfg(all(feature = "master", target_os = "espidf"))]
 pub mod master;
 
-#[cfg(feature = "slave")]
+#[cfg(all(feature = "slave", target_os = "espidf"))]
 pub mod slave;
 
 const KEYBOARD_ID: u8 = 0x01;
 const MEDIA_KEYS_ID: u8 = 0x02;
 const MOUSE_ID: u8 = 0x03;
+const SYSTEM_CONTROL_ID: u8 = 0x04;
+const NKRO_KEYBOARD_ID: u8 = 0x05;
+const ABSOLUTE_MOUSE_ID: u8 = 0x06;
+
+/// Bytes of the n-key rollover bitmap, one bit per keyboard usage 0x00 - 0xA7
+pub const NKRO_KEYS_BYTES: usize = 21;
 
+#[cfg(target_os = "espidf")]
 const HID_REPORT_DISCRIPTOR: &[u8] = hid!(
     (USAGE_PAGE, 0x01), // USAGE_PAGE (Generic Desktop Ctrls)
     (USAGE, 0x06),      // USAGE (Keyboard)
     (COLLECTION, 0x01), // COLLECTION (Application)
     // ------------------------------------------------- Keyboard
-    (REPORT_ID, KEYBOARD_ID), //   REPORT_ID (1)
-    (USAGE_PAGE, 0x07),       //   USAGE_PAGE (Kbrd/Keypad)
-    (USAGE_MINIMUM, 0xE0),    //   USAGE_MINIMUM (0xE0)
-    (USAGE_MAXIMUM, 0xE7),    //   USAGE_MAXIMUM (0xE7)
-    (LOGICAL_MINIMUM, 0x00),  //   LOGICAL_MINIMUM (0)
-    (LOGICAL_MAXIMUM, 0x01),  //   Logical Maximum (1)
-    (REPORT_SIZE, 0x01),      //   REPORT_SIZE (1)
-    (REPORT_COUNT, 0x08),     //   REPORT_COUNT (8)
+    (REPORT_ID, KEYBOARD_ID),      //   REPORT_ID (1)
+    (USAGE_PAGE, 0x07),            //   USAGE_PAGE (Kbrd/Keypad)
+    (USAGE_MINIMUM, 0xE0),         //   USAGE_MINIMUM (0xE0)
+    (USAGE_MAXIMUM, 0xE7),         //   USAGE_MAXIMUM (0xE7)
+    (LOGICAL_MINIMUM, 0x00),       //   LOGICAL_MINIMUM (0)
+    (LOGICAL_MAXIMUM, 0x01),       //   Logical Maximum (1)
+    (REPORT_SIZE, 0x01),           //   REPORT_SIZE (1)
+    (REPORT_COUNT, 0x08),          //   REPORT_COUNT (8)
     (HIDINPUT, 0x02), //   INPUT (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
     (REPORT_COUNT, 0x01), //   REPORT_COUNT (1) ; 1 byte (Reserved)
     (REPORT_SIZE, 0x08), //   REPORT_SIZE (8)
@@ -50,40 +61,45 @@ const HID_REPORT_DISCRIPTOR: &[u8] = hid!(
     (REPORT_COUNT, 0x06), //   REPORT_COUNT (6) ; 6 bytes (Keys)
     (REPORT_SIZE, 0x08), //   REPORT_SIZE(8)
     (LOGICAL_MINIMUM, 0x00), //   LOGICAL_MINIMUM(0)
-    (LOGICAL_MAXIMUM, 0x65), //   LOGICAL_MAXIMUM(0x65) ; 101 keys
-    (USAGE_PAGE, 0x07), //   USAGE_PAGE (Kbrd/Keypad)
+    (LOGICAL_MAXIMUM, 0xA4, 0x00), //   LOGICAL_MAXIMUM(0xA4) ; up to Exsel, 2 bytes as the maximum is signed
+    (USAGE_PAGE, 0x07),            //   USAGE_PAGE (Kbrd/Keypad)
+    (USAGE_MINIMUM, 0x00),         //   USAGE_MINIMUM (0)
+    (USAGE_MAXIMUM, 0xA4),         //   USAGE_MAXIMUM (0xA4)
+    (HIDINPUT, 0x00), //   INPUT (Data,Array,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    (END_COLLECTION), // END_COLLECTION
+    // ------------------------------------------------- N-Key Rollover Keyboard
+    (USAGE_PAGE, 0x01),            // USAGE_PAGE (Generic Desktop Ctrls)
+    (USAGE, 0x06),                 // USAGE (Keyboard)
+    (COLLECTION, 0x01),            // COLLECTION (Application)
+    (REPORT_ID, NKRO_KEYBOARD_ID), //   REPORT_ID (5)
+    (USAGE_PAGE, 0x07),            //   USAGE_PAGE (Kbrd/Keypad)
+    (USAGE_MINIMUM, 0xE0),         //   USAGE_MINIMUM (0xE0)
+    (USAGE_MAXIMUM, 0xE7),         //   USAGE_MAXIMUM (0xE7)
+    (LOGICAL_MINIMUM, 0x00),       //   LOGICAL_MINIMUM (0)
+    (LOGICAL_MAXIMUM, 0x01),       //   LOGICAL_MAXIMUM (1)
+    (REPORT_SIZE, 0x01),           //   REPORT_SIZE (1)
+    (REPORT_COUNT, 0x08),          //   REPORT_COUNT (8) ; 1 byte (Modifiers)
+    (HIDINPUT, 0x02), //   INPUT (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
     (USAGE_MINIMUM, 0x00), //   USAGE_MINIMUM (0)
-    (USAGE_MAXIMUM, 0x65), //   USAGE_MAXIMUM (0x65)
-    (HIDINPUT, 0x00),  //   INPUT (Data,Array,Abs,No Wrap,Linear,Preferred State,No Null Position)
-    (END_COLLECTION),  // END_COLLECTION
+    (USAGE_MAXIMUM, 0xA7), //   USAGE_MAXIMUM (0xA7)
+    (REPORT_SIZE, 0x01), //   REPORT_SIZE (1)
+    (REPORT_COUNT, 0xA8), //   REPORT_COUNT (168) ; 21 bytes (Keys bitmap)
+    (HIDINPUT, 0x02), //   INPUT (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    (END_COLLECTION), // END_COLLECTION
     // ------------------------------------------------- Media Keys
     (USAGE_PAGE, 0x0C), // USAGE_PAGE (Consumer)
     (USAGE, 0x01),      // USAGE (Consumer Control)
     (COLLECTION, 0x01), // COLLECTION (Application)
     // ---------------------------------------------------
-    (REPORT_ID, MEDIA_KEYS_ID), //   REPORT_ID (2)
-    (USAGE_PAGE, 0x0C),         //   USAGE_PAGE (Consumer)
-    (LOGICAL_MINIMUM, 0x00),    //   LOGICAL_MINIMUM (0)
-    (LOGICAL_MAXIMUM, 0x01),    //   LOGICAL_MAXIMUM (1)
-    (REPORT_SIZE, 0x01),        //   REPORT_SIZE (1)
-    (REPORT_COUNT, 0x10),       //   REPORT_COUNT (16)
-    (USAGE, 0xB5),              //   USAGE (Scan Next Track)     ; bit 0: 1
-    (USAGE, 0xB6),              //   USAGE (Scan Previous Track) ; bit 1: 2
-    (USAGE, 0xB7),              //   USAGE (Stop)                ; bit 2: 4
-    (USAGE, 0xCD),              //   USAGE (Play/Pause)          ; bit 3: 8
-    (USAGE, 0xE2),              //   USAGE (Mute)                ; bit 4: 16
-    (USAGE, 0xE9),              //   USAGE (Volume Increment)    ; bit 5: 32
-    (USAGE, 0xEA),              //   USAGE (Volume Decrement)    ; bit 6: 64
-    (USAGE, 0x23, 0x02),        //   Usage (WWW Home)            ; bit 7: 128
-    (USAGE, 0x94, 0x01),        //   Usage (My Computer) ; bit 0: 1
-    (USAGE, 0x92, 0x01),        //   Usage (Calculator)  ; bit 1: 2
-    (USAGE, 0x2A, 0x02),        //   Usage (WWW fav)     ; bit 2: 4
-    (USAGE, 0x21, 0x02),        //   Usage (WWW search)  ; bit 3: 8
-    (USAGE, 0x26, 0x02),        //   Usage (WWW stop)    ; bit 4: 16
-    (USAGE, 0x24, 0x02),        //   Usage (WWW back)    ; bit 5: 32
-    (USAGE, 0x83, 0x01),        //   Usage (Media sel)   ; bit 6: 64
-    (USAGE, 0x8A, 0x01),        //   Usage (Mail)        ; bit 7: 128
-    (HIDINPUT, 0x02), // INPUT (Data,Var,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    (REPORT_ID, MEDIA_KEYS_ID),    //   REPORT_ID (2)
+    (USAGE_PAGE, 0x0C),            //   USAGE_PAGE (Consumer)
+    (LOGICAL_MINIMUM, 0x00),       //   LOGICAL_MINIMUM (0)
+    (LOGICAL_MAXIMUM, 0xFF, 0x03), //   LOGICAL_MAXIMUM (0x3FF)
+    (USAGE_MINIMUM, 0x00),         //   USAGE_MINIMUM (0)
+    (USAGE_MAXIMUM, 0xFF, 0x03),   //   USAGE_MAXIMUM (0x3FF)
+    (REPORT_SIZE, 0x10),           //   REPORT_SIZE (16)
+    (REPORT_COUNT, 0x01),          //   REPORT_COUNT (1) ; 2 bytes (Consumer usage)
+    (HIDINPUT, 0x00), //   INPUT (Data,Array,Abs,No Wrap,Linear,Preferred State,No Null Position)
     (END_COLLECTION), // END_COLLECTION
     // ------------------------------------------------------------ Mouse
     (USAGE_PAGE, 0x01), // USAGE_PAGE (Generic Desktop)
@@ -105,26 +121,100 @@ const HID_REPORT_DISCRIPTOR: &[u8] = hid!(
     (REPORT_SIZE, 0x03),  //     REPORT_SIZE (3)
     (REPORT_COUNT, 0x01), //     REPORT_COUNT (1)
     (HIDINPUT, 0x03),     //     INPUT (Constant, Variable, Absolute) ;3 bit padding
-    // ------------------------------------------------- X/Y position, Wheel
+    // ------------------------------------------------- X/Y position
     (USAGE_PAGE, 0x01),      //     USAGE_PAGE (Generic Desktop)
     (USAGE, 0x30),           //     USAGE (X)
     (USAGE, 0x31),           //     USAGE (Y)
-    (USAGE, 0x38, 0x02),     //     USAGE (Wheel)
     (LOGICAL_MINIMUM, 0x81), //     LOGICAL_MINIMUM (-127)
     (LOGICAL_MAXIMUM, 0x7f), //     LOGICAL_MAXIMUM (127)
     (REPORT_SIZE, 0x08),     //     REPORT_SIZE (8)
-    (REPORT_COUNT, 0x03),    //     REPORT_COUNT (3)
-    (HIDINPUT, 0x06),        //     INPUT (Data, Variable, Relative) ;3 bytes (X,Y,Wheel)
+    (REPORT_COUNT, 0x02),    //     REPORT_COUNT (2)
+    (HIDINPUT, 0x06),        //     INPUT (Data, Variable, Relative) ;2 bytes (X,Y)
+    // ------------------------------------------------- Wheel
+    (COLLECTION, 0x02),                            //     COLLECTION (Logical)
+    (USAGE, 0x48),                                 //       USAGE (Resolution Multiplier)
+    (LOGICAL_MINIMUM, 0x00),                       //       LOGICAL_MINIMUM (0)
+    (LOGICAL_MAXIMUM, 0x01),                       //       LOGICAL_MAXIMUM (1)
+    (PHYSICAL_MINIMUM, 0x01),                      //      PHYSICAL_MINIMUM (1)
+    (PHYSICAL_MAXIMUM, MOUSEKEY_WHEEL_RESOLUTION), // PHYSICAL_MAXIMUM (wheel units per notch)
+    (REPORT_SIZE, 0x02),                           //       REPORT_SIZE (2)
+    (REPORT_COUNT, 0x01),                          //       REPORT_COUNT (1)
+    (FEATURE, 0x02), //       FEATURE (Data, Variable, Absolute) ;2 bits (Wheel multiplier)
+    (USAGE, 0x38),   //       USAGE (Wheel)
+    (LOGICAL_MINIMUM, 0x81), //       LOGICAL_MINIMUM (-127)
+    (LOGICAL_MAXIMUM, 0x7f), //       LOGICAL_MAXIMUM (127)
+    (PHYSICAL_MINIMUM, 0x00), //      PHYSICAL_MINIMUM (0)
+    (PHYSICAL_MAXIMUM, 0x00), //      PHYSICAL_MAXIMUM (0)
+    (REPORT_SIZE, 0x08), //       REPORT_SIZE (8)
+    (HIDINPUT, 0x06), //       INPUT (Data, Variable, Relative) ;1 byte (Wheel)
+    (END_COLLECTION), //     END_COLLECTION
     // ------------------------------------------------- Horizontal wheel
-    (USAGE_PAGE, 0x0c),      //     USAGE PAGE (Consumer Devices)
-    (USAGE, 0x38),           //     USAGE (AC Pan)
-    (LOGICAL_MINIMUM, 0x81), //     LOGICAL_MINIMUM (-127)
-    (LOGICAL_MAXIMUM, 0x7f), //     LOGICAL_MAXIMUM (127)
-    (REPORT_SIZE, 0x08),     //     REPORT_SIZE (8)
-    (REPORT_COUNT, 0x01),    //     REPORT_COUNT (1)
-    (HIDINPUT, 0x06),        //     INPUT (Data, Var, Rel)
-    (END_COLLECTION),        //   END_COLLECTION
-    (END_COLLECTION),        //   END_COLLECTION
+    (COLLECTION, 0x02),                            //     COLLECTION (Logical)
+    (USAGE, 0x48),                                 //       USAGE (Resolution Multiplier)
+    (LOGICAL_MINIMUM, 0x00),                       //       LOGICAL_MINIMUM (0)
+    (LOGICAL_MAXIMUM, 0x01),                       //       LOGICAL_MAXIMUM (1)
+    (PHYSICAL_MINIMUM, 0x01),                      //      PHYSICAL_MINIMUM (1)
+    (PHYSICAL_MAXIMUM, MOUSEKEY_WHEEL_RESOLUTION), // PHYSICAL_MAXIMUM (wheel units per notch)
+    (REPORT_SIZE, 0x02),                           //       REPORT_SIZE (2)
+    (FEATURE, 0x02), //       FEATURE (Data, Variable, Absolute) ;2 bits (AC Pan multiplier)
+    (USAGE_PAGE, 0x0c), //       USAGE PAGE (Consumer Devices)
+    (USAGE, 0x38, 0x02), //       USAGE (AC Pan)
+    (LOGICAL_MINIMUM, 0x81), //       LOGICAL_MINIMUM (-127)
+    (LOGICAL_MAXIMUM, 0x7f), //       LOGICAL_MAXIMUM (127)
+    (PHYSICAL_MINIMUM, 0x00), //      PHYSICAL_MINIMUM (0)
+    (PHYSICAL_MAXIMUM, 0x00), //      PHYSICAL_MAXIMUM (0)
+    (REPORT_SIZE, 0x08), //       REPORT_SIZE (8)
+    (HIDINPUT, 0x06), //       INPUT (Data, Var, Rel) ;1 byte (AC Pan)
+    (END_COLLECTION), //     END_COLLECTION
+    // ------------------------------------------------- Feature padding
+    (REPORT_SIZE, 0x04), //     REPORT_SIZE (4)
+    (FEATURE, 0x03),     //     FEATURE (Constant, Variable, Absolute) ;4 bit padding
+    (END_COLLECTION),    //   END_COLLECTION
+    (END_COLLECTION),    //   END_COLLECTION
+    // ------------------------------------------------------------ System Control
+    (USAGE_PAGE, 0x01),             // USAGE_PAGE (Generic Desktop)
+    (USAGE, 0x80),                  // USAGE (System Control)
+    (COLLECTION, 0x01),             // COLLECTION (Application)
+    (REPORT_ID, SYSTEM_CONTROL_ID), //   REPORT_ID (4)
+    (LOGICAL_MINIMUM, 0x00),        //   LOGICAL_MINIMUM (0)
+    (LOGICAL_MAXIMUM, 0xB7, 0x00),  //   LOGICAL_MAXIMUM (0xB7)
+    (USAGE_MINIMUM, 0x00),          //   USAGE_MINIMUM (0)
+    (USAGE_MAXIMUM, 0xB7),          //   USAGE_MAXIMUM (0xB7)
+    (REPORT_SIZE, 0x08),            //   REPORT_SIZE (8)
+    (REPORT_COUNT, 0x01),           //   REPORT_COUNT (1) ; 1 byte (System control usage)
+    (HIDINPUT, 0x00), //   INPUT (Data,Array,Abs,No Wrap,Linear,Preferred State,No Null Position)
+    (END_COLLECTION), // END_COLLECTION
+    // ------------------------------------------------------------ Absolute Mouse
+    (USAGE_PAGE, 0x01), // USAGE_PAGE (Generic Desktop)
+    (USAGE, 0x02),      // USAGE (Mouse)
+    (COLLECTION, 0x01), // COLLECTION (Application)
+    (USAGE, 0x01),      //   USAGE (Pointer)
+    (COLLECTION, 0x00), //   COLLECTION (Physical)
+    // ------------------------------------------------- Buttons (always released, clicks use the mouse report)
+    (REPORT_ID, ABSOLUTE_MOUSE_ID), // REPORT_ID (6)
+    (USAGE_PAGE, 0x09),             //     USAGE_PAGE (Button)
+    (USAGE_MINIMUM, 0x01),          //     USAGE_MINIMUM (Button 1)
+    (USAGE_MAXIMUM, 0x05),          //     USAGE_MAXIMUM (Button 5)
+    (LOGICAL_MINIMUM, 0x00),        //     LOGICAL_MINIMUM (0)
+    (LOGICAL_MAXIMUM, 0x01),        //     LOGICAL_MAXIMUM (1)
+    (REPORT_SIZE, 0x01),            //     REPORT_SIZE (1)
+    (REPORT_COUNT, 0x05),           //     REPORT_COUNT (5)
+    (HIDINPUT, 0x02),               //     INPUT (Data, Variable, Absolute) ;5 button bits
+    // ------------------------------------------------- Padding
+    (REPORT_SIZE, 0x03),  //     REPORT_SIZE (3)
+    (REPORT_COUNT, 0x01), //     REPORT_COUNT (1)
+    (HIDINPUT, 0x03),     //     INPUT (Constant, Variable, Absolute) ;3 bit padding
+    // ------------------------------------------------- X/Y position
+    (USAGE_PAGE, 0x01),            //     USAGE_PAGE (Generic Desktop)
+    (USAGE, 0x30),                 //     USAGE (X)
+    (USAGE, 0x31),                 //     USAGE (Y)
+    (LOGICAL_MINIMUM, 0x00),       //     LOGICAL_MINIMUM (0)
+    (LOGICAL_MAXIMUM, 0xFF, 0x7F), //     LOGICAL_MAXIMUM (32767)
+    (REPORT_SIZE, 0x10),           //     REPORT_SIZE (16)
+    (REPORT_COUNT, 0x02),          //     REPORT_COUNT (2)
+    (HIDINPUT, 0x02),              //     INPUT (Data, Variable, Absolute) ;4 bytes (X,Y)
+    (END_COLLECTION),              //   END_COLLECTION
+    (END_COLLECTION),              // END_COLLECTION
 );
 
 #[derive(Debug, Default, PartialEq, Clone, Copy, IntoBytes, Immutable)]
@@ -135,25 +225,174 @@ pub struct KeyboardKeyReport {
     pub keys: [u8; 6],
 }
 
+/// N-key rollover keyboard report
+///
+/// Holds the bitmap of the pressed keys, the modifiers are taken from the 6-key report.
+/// The 6-key boot report is sent until the n-key rollover is switched on.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct NkroKeyReport {
+    keys: [u8; NKRO_KEYS_BYTES],
+    enabled: bool,
+}
+
+impl Default for NkroKeyReport {
+    fn default() -> Self {
+        Self {
+            keys: [0; NKRO_KEYS_BYTES],
+            enabled: NKRO_ENABLED,
+        }
+    }
+}
+
+impl NkroKeyReport {
+    /// Store the report in an array that is ready to be sent,
+    /// the keys of the 6-key report (e.g. from the macros) are added to the bitmap
+    pub fn construct(&self, keyboard_key_report: &KeyboardKeyReport) -> [u8; NKRO_KEYS_BYTES + 1] {
+        let mut report = [0; NKRO_KEYS_BYTES + 1];
+        report[0] = keyboard_key_report.modifiers;
+        report[1..].copy_from_slice(&self.keys);
+
+        for hid_code in keyboard_key_report.keys {
+            if hid_code != 0 && (hid_code as usize) < NKRO_KEYS_BYTES * 8 {
+                report[1 + hid_code as usize / 8] |= 1 << (hid_code % 8);
+            }
+        }
+
+        report
+    }
+
+    /// Set the bit of the pressed key
+    pub fn add_key(&mut self, hid_code: u8) {
+        if let Some(byte) = self.keys.get_mut(hid_code as usize / 8) {
+            *byte |= 1 << (hid_code % 8);
+        }
+    }
+
+    /// Clear the bit of the released key
+    pub fn remove_key(&mut self, hid_code: u8) {
+        if let Some(byte) = self.keys.get_mut(hid_code as usize / 8) {
+            *byte &= !(1 << (hid_code % 8));
+        }
+    }
+
+    /// Check if the n-key rollover report is sent instead of the 6-key report
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Switch between the 6-key and the n-key rollover report
+    pub fn set_command(&mut self, nkro_action: &NkroAction) {
+        match *nkro_action {
+            NkroAction::On => self.enabled = true,
+            NkroAction::Off => self.enabled = false,
+            NkroAction::Toggle => self.enabled = !self.enabled,
+        }
+    }
+}
+
+/// Consumer page report, holds the usage of the last pressed media key
+#[derive(Debug, Default, PartialEq, Clone, Copy, IntoBytes, Immutable)]
+#[repr(packed, C)]
+pub struct MediaKeyReport {
+    pub usage: u16,
+}
+
+/// System control report, holds the usage of the last pressed power, sleep or wake up key
+#[derive(Debug, Default, PartialEq, Clone, Copy, IntoBytes, Immutable)]
+#[repr(packed, C)]
+pub struct SystemControlReport {
+    pub usage: u8,
+}
+
+#[cfg(target_os = "espidf")]
 pub struct BleKeyboardMaster {
     server: &'static mut BLEServer,
     input_slave: Arc<Mutex<BLECharacteristic>>,
+    output_slave_host_led: Arc<Mutex<BLECharacteristic>>,
     input_keyboard: Arc<Mutex<BLECharacteristic>>,
+    input_nkro_keyboard: Arc<Mutex<BLECharacteristic>>,
     output_keyboard: Arc<Mutex<BLECharacteristic>>,
+    input_boot_keyboard: Arc<Mutex<BLECharacteristic>>,
+    output_boot_keyboard: Arc<Mutex<BLECharacteristic>>,
     input_media_keys: Arc<Mutex<BLECharacteristic>>,
+    input_system_control: Arc<Mutex<BLECharacteristic>>,
     input_mouse: Arc<Mutex<BLECharacteristic>>,
+    feature_mouse: Arc<Mutex<BLECharacteristic>>,
+    input_absolute_mouse: Arc<Mutex<BLECharacteristic>>,
+    protocol_mode: Arc<Mutex<ProtocolMode>>,
+    wheel_resolution: Arc<Mutex<u8>>,
     current_keyboard_report: KeyboardKeyReport,
     previous_keyboard_report: KeyboardKeyReport,
+    current_nkro_report: [u8; NKRO_KEYS_BYTES + 1],
+    previous_nkro_report: [u8; NKRO_KEYS_BYTES + 1],
+    current_media_report: MediaKeyReport,
+    previous_media_report: MediaKeyReport,
+    current_system_control_report: SystemControlReport,
+    previous_system_control_report: SystemControlReport,
     current_mouse_report: MouseKeyReport,
     previous_mouse_report: MouseKeyReport,
 }
 
+#[cfg(target_os = "espidf")]
 pub struct BleKeyboardSlave {
     client: BLEClient,
     current_pressed_keys: [u8; 6],
     previous_pressed_keys: [u8; 6],
 }
 
+/// LED state written by the host to the keyboard output report
+///
+/// Shared with the other tasks, so they can follow the lock keys of the host
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HostLedState {
+    leds: u8,
+}
+
+impl HostLedState {
+    const NUM_LOCK: u8 = 0x01;
+    const CAPS_LOCK: u8 = 0x02;
+    const SCROLL_LOCK: u8 = 0x04;
+    const COMPOSE: u8 = 0x08;
+    const KANA: u8 = 0x10;
+
+    /// Store the LED bits of the received output report
+    pub fn set(&mut self, leds: u8) {
+        self.leds = leds;
+    }
+
+    /// Get the LED bits, as received in the output report
+    pub fn leds(&self) -> u8 {
+        self.leds
+    }
+
+    pub fn num_lock(&self) -> bool {
+        self.leds & Self::NUM_LOCK != 0
+    }
+
+    pub fn caps_lock(&self) -> bool {
+        self.leds & Self::CAPS_LOCK != 0
+    }
+
+    pub fn scroll_lock(&self) -> bool {
+        self.leds & Self::SCROLL_LOCK != 0
+    }
+
+    pub fn compose(&self) -> bool {
+        self.leds & Self::COMPOSE != 0
+    }
+
+    pub fn kana(&self) -> bool {
+        self.leds & Self::KANA != 0
+    }
+}
+
+/// HID protocol mode, set by the host through the protocol mode characteristic
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProtocolMode {
+    Boot = 0x00,
+    Report = 0x01,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum BleStatus {
     Connected,
@@ -197,3 +436,75 @@ impl Debounce {
         self.previous_instant = Instant::now() + debounce_duraiton;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_set_their_bit_in_the_bitmap() {
+        let mut report = NkroKeyReport::default();
+
+        report.add_key(0x04);
+        report.add_key(0x0F);
+        report.add_key(0xA7);
+
+        let bitmap = report.construct(&KeyboardKeyReport::default());
+        assert_eq!(bitmap[1..3], [0b0001_0000, 0b1000_0000]);
+        assert_eq!(bitmap[NKRO_KEYS_BYTES], 0b1000_0000);
+
+        report.remove_key(0x0F);
+        let bitmap = report.construct(&KeyboardKeyReport::default());
+        assert_eq!(bitmap[1..3], [0b0001_0000, 0]);
+    }
+
+    #[test]
+    fn keys_past_the_bitmap_are_ignored() {
+        let mut report = NkroKeyReport::default();
+
+        report.add_key(0xA8);
+        report.add_key(0xE0);
+        report.remove_key(0xFF);
+
+        assert_eq!(report, NkroKeyReport::default());
+    }
+
+    #[test]
+    fn construct_adds_the_modifiers_and_the_keys_of_the_6_key_report() {
+        let mut report = NkroKeyReport::default();
+        report.add_key(0x04);
+
+        let keyboard_key_report = KeyboardKeyReport {
+            modifiers: 0x02,
+            reserved: 0,
+            keys: [0x05, 0xA7, 0xA8, 0, 0, 0],
+        };
+        let bitmap = report.construct(&keyboard_key_report);
+
+        assert_eq!(bitmap[0], 0x02);
+        assert_eq!(bitmap[1], 0b0011_0000);
+        assert_eq!(bitmap[NKRO_KEYS_BYTES], 0b1000_0000);
+
+        // the empty slots do not set the bit of usage 0
+        assert_eq!(bitmap[1] & 0x01, 0);
+        assert_eq!(bitmap[2..NKRO_KEYS_BYTES], [0; NKRO_KEYS_BYTES - 2]);
+    }
+
+    #[test]
+    fn nkro_actions_switch_the_report() {
+        let mut report = NkroKeyReport::default();
+        assert_eq!(report.is_enabled(), NKRO_ENABLED);
+
+        report.set_command(&NkroAction::On);
+        assert!(report.is_enabled());
+
+        report.set_command(&NkroAction::Toggle);
+        assert!(!report.is_enabled());
+
+        report.set_command(&NkroAction::Off);
+        assert!(!report.is_enabled());
+
+        report.set_command(&NkroAction::Toggle);
+        assert!(report.is_enabled());
+    }
+}
@@ -6,7 +6,7 @@ use crate::key_provision::key_provision;
 use crate::matrix::{KeyPos, RegisteredMatrixKeys};
 
 extern crate alloc;
-use super::{BleKeyboardSlave, BleStatus};
+use super::{BleKeyboardSlave, BleStatus, HostLedState};
 use alloc::sync::Arc;
 use esp32_nimble::{enums::*, utilities::mutex::Mutex, uuid128, BLEAddress, BLEDevice};
 use esp_idf_sys::{
@@ -63,6 +63,54 @@ impl BleKeyboardSlave {
             .expect("Unable to write new data to the ble_characteristic!");
     }
 
+    /// Keep the host LED state current, the master notifies every change
+    ///
+    /// Without the host LED characteristic, the keys are still sent and the LED state is left unset
+    async fn subscribe_host_led_state(&mut self, host_led_state: &Arc<Mutex<HostLedState>>) {
+        let service = match self
+            .client
+            .get_service(uuid128!("fafafafa-fafa-fafa-fafa-fafafafafafa"))
+            .await
+        {
+            Ok(service) => service,
+            Err(error) => {
+                log::error!("Unable to find the master service: {error:?}");
+                return;
+            }
+        };
+
+        let remote_characteristic = match service.get_characteristic(BLE_HOST_LED_UUID).await {
+            Ok(remote_characteristic) => remote_characteristic,
+            Err(error) => {
+                log::error!("Unable to find the host LED state characteristic: {error:?}");
+                return;
+            }
+        };
+
+        remote_characteristic.on_notify({
+            let host_led_state = Arc::clone(host_led_state);
+            move |data| {
+                if let Some(leds) = data.first() {
+                    host_led_state.lock().set(*leds);
+                }
+            }
+        });
+
+        if let Err(error) = remote_characteristic.subscribe_notify(false).await {
+            log::error!("Unable to subscribe to the host LED state: {error:?}");
+        }
+
+        // only the changes are notified, read the state the host has set before the subscription
+        match remote_characteristic.read_value().await {
+            Ok(data) => {
+                if let Some(leds) = data.first() {
+                    host_led_state.lock().set(*leds);
+                }
+            }
+            Err(error) => log::error!("Unable to read the host LED state: {error:?}"),
+        }
+    }
+
     pub fn set_ble_power_save(&mut self) {
         //set power save
         unsafe {
@@ -93,10 +141,16 @@ impl BleKeyboardSlave {
 pub async fn ble_tx(
     pressed_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
     ble_status: &Arc<Mutex<BleStatus>>,
+    host_led_state: &Arc<Mutex<HostLedState>>,
 ) -> ! {
     // construct ble slave
     let mut ble_keyboard_slave: BleKeyboardSlave = BleKeyboardSlave::new().await;
 
+    // receive the host LED state from the master
+    ble_keyboard_slave
+        .subscribe_host_led_state(host_led_state)
+        .await;
+
     // set ble power to lowest possible
     // ble_keyboard_slave.set_ble_power_save();
 
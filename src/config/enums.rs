@@ -1,390 +1,713 @@
 /* Scan codes - HID Keyboard: https://gist.github.com/MightyPork/6da26e382a7ad91b5496ee55fdc73db2 */
 
-use heapless::Vec;
-
+/// HID usage of the keyboard page, only used when the reports are built
+#[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Default)]
-pub enum Kc {
-    None = 0x00, // None
+pub enum HidKey {
     #[default]
-    Undf = 0x03, // Undefined
-    A = 0x04,    // A
-    B = 0x05,    // B
-    C = 0x06,    // C
-    D = 0x07,    // D
-    E = 0x08,    // E
-    F = 0x09,    // F
-    G = 0x0A,    // G
-    H = 0x0B,    // H
-    I = 0x0C,    // I
-    J = 0x0D,    // J
-    K = 0x0E,    // K
-    L = 0x0F,    // L
-    M = 0x10,    // M
-    N = 0x11,    // N
-    O = 0x12,    // O
-    P = 0x13,    // P
-    Q = 0x14,    // Q
-    R = 0x15,    // R
-    S = 0x16,    // S
-    T = 0x17,    // T
-    U = 0x18,    // U
-    V = 0x19,    // V
-    W = 0x1A,    // W
-    X = 0x1B,    // X
-    Y = 0x1C,    // Y
-    Z = 0x1D,    // Z
-    N1 = 0x1E,   // Num1
-    N2 = 0x1F,   // Num2
-    N3 = 0x20,   // Num3
-    N4 = 0x21,   // Num4
-    N5 = 0x22,   // Num5
-    N6 = 0x23,   // Num6
-    N7 = 0x24,   // Num7
-    N8 = 0x25,   // Num8
-    N9 = 0x26,   // Num9
-    N0 = 0x27,   // Num0
-    Entr = 0x28, // Enter
-    Esc = 0x29,  // Escape
-    Bksp = 0x2A, // BackSpace
-    Tab = 0x2B,  // Tab
-    Spac = 0x2C, // Space
-    Mns = 0x2D,  // Minus
-    Eq = 0x2E,   // Equal
-    Lbrk = 0x2F, // LeftBracket
-    Rbrk = 0x30, // RightBracket
-    Bksl = 0x31, // BackSlash
-    Nsh = 0x32,  // NonusHash
-    Scn = 0x33,  // SemiColon
-    Qte = 0x34,  // Quote
-    Grav = 0x35, // Grave
-    Com = 0x36,  // Comma
-    Per = 0x37,  // Period
-    Fsl = 0x38,  // ForwardSlash
-    Caps = 0x39, // Capslock
-    F1 = 0x3A,   // F1
-    F2 = 0x3B,   // F2
-    F3 = 0x3C,   // F3
-    F4 = 0x3D,   // F4
-    F5 = 0x3E,   // F5
-    F6 = 0x3F,   // F6
-    F7 = 0x40,   // F7
-    F8 = 0x41,   // F8
-    F9 = 0x42,   // F9
-    F10 = 0x43,  // F10
-    F11 = 0x44,  // F11
-    F12 = 0x45,  // F12
-    Pscr = 0x46, // Pscreen
-    Scll = 0x47, // Scrolllock
-    Pse = 0x48,  // Pause
-    Ins = 0x49,  // Insert
-    Home = 0x4A, // Home
-    Pgup = 0x4B, // Pgup
-    Del = 0x4C,  // Delete
-    End = 0x4D,  // End
-    Pgdn = 0x4E, // Pgdown
-    ArR = 0x4F,  // ArrowRight
-    ArL = 0x50,  // ArrowLeft
-    ArD = 0x51,  // ArrowDown
-    ArU = 0x52,  // ArrowUp
-    Nlk = 0x53,  // Numlock
-    KpS = 0x54,  // KpSlash
-    KpA = 0x55,  // KpAsterisk
-    KpM = 0x56,  // KpMinus
-    KpP = 0x57,  // KpPlus
-    KpE = 0x58,  // KpEnter
-    Kp1 = 0x59,  // Kp1
-    Kp2 = 0x5A,  // Kp2
-    Kp3 = 0x5B,  // Kp3
-    Kp4 = 0x5C,  // Kp4
-    Kp5 = 0x5D,  // Kp5
-    Kp6 = 0x5E,  // Kp6
-    Kp7 = 0x5F,  // Kp7
-    Kp8 = 0x60,  // Kp8
-    Kp9 = 0x61,  // Kp9
-    Kp0 = 0x62,  // Kp0
-    KpD = 0x63,  // KpDot
-    Nbl = 0x64,  // NonusBslash
-    App = 0x65,  // Application
-    Pwr = 0x66,  // Power
-    KpEql = 0x67, // KpEqual
-    F13 = 0x68,  // F13
-    F14 = 0x69,  // F14
-    F15 = 0x6A,  // F15
-    F16 = 0x6B,  // F16
-    F17 = 0x6C,  // F17
-    F18 = 0x6D,  // F18
-    F19 = 0x6E,  // F19
-    F20 = 0x6F,  // F20
-    F21 = 0x70,  // F21
-    F22 = 0x71,  // F22
-    F23 = 0x72,  // F23
-    F24 = 0x73,  // F24
-    Exe = 0x74,  // Execute
-    Help = 0x75, // Help
-    Menu = 0x76, // Menu
-    Sel = 0x77,  // Select
-    Stp = 0x78,  // Stop
-    Agn = 0x79,  // Again
-    Und = 0x7A,  // Undo
-    Cut = 0x7B,  // Cut
-    Cop = 0x7C,  // Copy
-    Pas = 0x7D,  // Paste
-    Fin = 0x7E,  // Find
-    Mute = 0x7F, // Mute
-    Vup = 0x80,  // Volup
-    Vdown = 0x81, // Voldown
-    LckC = 0x82, // LockingCaps
-    LckN = 0x83, // LockingNum
-    LckS = 0x84, // LockingScroll
-    KpC = 0x85,  // KpComma
+    None = 0x00, // None
+    A = 0x04,        // A
+    B = 0x05,        // B
+    C = 0x06,        // C
+    D = 0x07,        // D
+    E = 0x08,        // E
+    F = 0x09,        // F
+    G = 0x0A,        // G
+    H = 0x0B,        // H
+    I = 0x0C,        // I
+    J = 0x0D,        // J
+    K = 0x0E,        // K
+    L = 0x0F,        // L
+    M = 0x10,        // M
+    N = 0x11,        // N
+    O = 0x12,        // O
+    P = 0x13,        // P
+    Q = 0x14,        // Q
+    R = 0x15,        // R
+    S = 0x16,        // S
+    T = 0x17,        // T
+    U = 0x18,        // U
+    V = 0x19,        // V
+    W = 0x1A,        // W
+    X = 0x1B,        // X
+    Y = 0x1C,        // Y
+    Z = 0x1D,        // Z
+    N1 = 0x1E,       // Num1
+    N2 = 0x1F,       // Num2
+    N3 = 0x20,       // Num3
+    N4 = 0x21,       // Num4
+    N5 = 0x22,       // Num5
+    N6 = 0x23,       // Num6
+    N7 = 0x24,       // Num7
+    N8 = 0x25,       // Num8
+    N9 = 0x26,       // Num9
+    N0 = 0x27,       // Num0
+    Entr = 0x28,     // Enter
+    Esc = 0x29,      // Escape
+    Bksp = 0x2A,     // BackSpace
+    Tab = 0x2B,      // Tab
+    Spac = 0x2C,     // Space
+    Mns = 0x2D,      // Minus
+    Eq = 0x2E,       // Equal
+    Lbrk = 0x2F,     // LeftBracket
+    Rbrk = 0x30,     // RightBracket
+    Bksl = 0x31,     // BackSlash
+    Nsh = 0x32,      // NonusHash
+    Scn = 0x33,      // SemiColon
+    Qte = 0x34,      // Quote
+    Grav = 0x35,     // Grave
+    Com = 0x36,      // Comma
+    Per = 0x37,      // Period
+    Fsl = 0x38,      // ForwardSlash
+    Caps = 0x39,     // Capslock
+    F1 = 0x3A,       // F1
+    F2 = 0x3B,       // F2
+    F3 = 0x3C,       // F3
+    F4 = 0x3D,       // F4
+    F5 = 0x3E,       // F5
+    F6 = 0x3F,       // F6
+    F7 = 0x40,       // F7
+    F8 = 0x41,       // F8
+    F9 = 0x42,       // F9
+    F10 = 0x43,      // F10
+    F11 = 0x44,      // F11
+    F12 = 0x45,      // F12
+    Pscr = 0x46,     // Pscreen
+    Scll = 0x47,     // Scrolllock
+    Pse = 0x48,      // Pause
+    Ins = 0x49,      // Insert
+    Home = 0x4A,     // Home
+    Pgup = 0x4B,     // Pgup
+    Del = 0x4C,      // Delete
+    End = 0x4D,      // End
+    Pgdn = 0x4E,     // Pgdown
+    ArR = 0x4F,      // ArrowRight
+    ArL = 0x50,      // ArrowLeft
+    ArD = 0x51,      // ArrowDown
+    ArU = 0x52,      // ArrowUp
+    Nlk = 0x53,      // Numlock
+    KpS = 0x54,      // KpSlash
+    KpA = 0x55,      // KpAsterisk
+    KpM = 0x56,      // KpMinus
+    KpP = 0x57,      // KpPlus
+    KpE = 0x58,      // KpEnter
+    Kp1 = 0x59,      // Kp1
+    Kp2 = 0x5A,      // Kp2
+    Kp3 = 0x5B,      // Kp3
+    Kp4 = 0x5C,      // Kp4
+    Kp5 = 0x5D,      // Kp5
+    Kp6 = 0x5E,      // Kp6
+    Kp7 = 0x5F,      // Kp7
+    Kp8 = 0x60,      // Kp8
+    Kp9 = 0x61,      // Kp9
+    Kp0 = 0x62,      // Kp0
+    KpD = 0x63,      // KpDot
+    Nbl = 0x64,      // NonusBslash
+    App = 0x65,      // Application
+    Pwr = 0x66,      // Power
+    KpEql = 0x67,    // KpEqual
+    F13 = 0x68,      // F13
+    F14 = 0x69,      // F14
+    F15 = 0x6A,      // F15
+    F16 = 0x6B,      // F16
+    F17 = 0x6C,      // F17
+    F18 = 0x6D,      // F18
+    F19 = 0x6E,      // F19
+    F20 = 0x6F,      // F20
+    F21 = 0x70,      // F21
+    F22 = 0x71,      // F22
+    F23 = 0x72,      // F23
+    F24 = 0x73,      // F24
+    Exe = 0x74,      // Execute
+    Help = 0x75,     // Help
+    Menu = 0x76,     // Menu
+    Sel = 0x77,      // Select
+    Stp = 0x78,      // Stop
+    Agn = 0x79,      // Again
+    Und = 0x7A,      // Undo
+    Cut = 0x7B,      // Cut
+    Cop = 0x7C,      // Copy
+    Pas = 0x7D,      // Paste
+    Fin = 0x7E,      // Find
+    Mute = 0x7F,     // Mute
+    Vup = 0x80,      // Volup
+    Vdown = 0x81,    // Voldown
+    LckC = 0x82,     // LockingCaps
+    LckN = 0x83,     // LockingNum
+    LckS = 0x84,     // LockingScroll
+    KpC = 0x85,      // KpComma
     KpEql400 = 0x86, // KpEqualAs400
-    Int1 = 0x87, // Int1
-    Int2 = 0x88, // Int2
-    Int3 = 0x89, // Int3
-    Int4 = 0x8A, // Int4
-    Int5 = 0x8B, // Int5
-    Int6 = 0x8C, // Int6
-    Int7 = 0x8D, // Int7
-    Int8 = 0x8E, // Int8
-    Int9 = 0x8F, // Int9
-    Lg1 = 0x90,  // Lang1
-    Lg3 = 0x92,  // Lang3
-    Lg4 = 0x93,  // Lang4
-    Lg5 = 0x94,  // Lang5
-    Lg6 = 0x95,  // Lang6
-    Lg7 = 0x96,  // Lang7
-    Lg8 = 0x97,  // Lang8
-    Lg9 = 0x98,  // Lang9
-    AltE = 0x99, // AltErase
-    Sys = 0x9A,  // Sysreq
-    Canc = 0x9B, // Cancel
-    Clr = 0x9C,  // Clear
-    Pri = 0x9D,  // Prior
-    Ret = 0x9E,  // Return
-    Sep = 0x9F,  // Separator
-    Out = 0xA0,  // Out
-    Oper = 0xA1, // Oper
-    ClrA = 0xA2, // ClearAgain
-    Crs = 0xA3,  // Crsel
-    Exs = 0xA4,  // Exsel
-
-    // dummy layer
-    L1 = 0xA5, // Layer1
-    L2 = 0xA6, // Layer2
-    L3 = 0xA7, // Layer3
-    L4 = 0xA8, // Layer4
-    L5 = 0xA9, // Layer5
-
-    // dummy modifiers
-    ModSh = 0xB0, // ModifierShift
-    ModCo = 0xB1, // ModifierControl
-    ModAl = 0xB2, // ModifierAlt
-    ModSu = 0xB3, // ModifierSuper
-
-    // dummy macros
-    MaLP = 0xC0,   // MacroLeftParenthesis
-    MaRP = 0xC1,   // MacroRightParenthesis
-    MaCp = 0xC2,   // MacroCopy
-    MaPa = 0xC3,   // MacroPaste
-    MaEx = 0xC4,   // MacroExclamationMark
-    MaAt = 0xC5,   // MacroAt
-    MaHs = 0xC6,   // MacroHash
-    MaDl = 0xC7,   // MacroDollar
-    MaMd = 0xC8,   // MacroModul
-    MaCa = 0xC9,   // MacroCaret
-    MaAmp = 0xCA,  // MacroAmpersand
-    MaAst = 0xCB,  // MacroAsterix
-    MaSL = 0xCC,   // MacroSuperLock
-    MaLB = 0xCD,   // MacroLeftBrace
-    MaRB = 0xCE,   // MacroRightBrace
-    MaPipe = 0xDD, // MacroPipe
-
-    // dummy mouse controls
-    MoGL = 0xD0, // MouseGoLeft
-    MoGD = 0xD1, // MouseGoDown
-    MoGU = 0xD2, // MouseGoUp
-    MoGR = 0xD3, // MouseGoRight
-    MoLC = 0xD4, // MouseLeftClick
-    MoRC = 0xD5, // MouseRightClick
-    MoSL = 0xD6, // MouseScrollLeft
-    MoSR = 0xD7, // MouseScrollRight
-    MoSU = 0xD8, // MouseScrollUp
-    MoSD = 0xD9, // MouseScrollDown
-    MoCF = 0xDA, // MouseCursorFast
-    MoCN = 0xDB, // MouseCursorNormal
-    MoCS = 0xDC, // MouseCursorSlow
-
-    // dummy combos
-    ComboCtrlD = 0xF0, // ComboControlD = ctrl+backspace
+    Int1 = 0x87,     // Int1
+    Int2 = 0x88,     // Int2
+    Int3 = 0x89,     // Int3
+    Int4 = 0x8A,     // Int4
+    Int5 = 0x8B,     // Int5
+    Int6 = 0x8C,     // Int6
+    Int7 = 0x8D,     // Int7
+    Int8 = 0x8E,     // Int8
+    Int9 = 0x8F,     // Int9
+    Lg1 = 0x90,      // Lang1
+    Lg3 = 0x92,      // Lang3
+    Lg4 = 0x93,      // Lang4
+    Lg5 = 0x94,      // Lang5
+    Lg6 = 0x95,      // Lang6
+    Lg7 = 0x96,      // Lang7
+    Lg8 = 0x97,      // Lang8
+    Lg9 = 0x98,      // Lang9
+    AltE = 0x99,     // AltErase
+    Sys = 0x9A,      // Sysreq
+    Canc = 0x9B,     // Cancel
+    Clr = 0x9C,      // Clear
+    Pri = 0x9D,      // Prior
+    Ret = 0x9E,      // Return
+    Sep = 0x9F,      // Separator
+    Out = 0xA0,      // Out
+    Oper = 0xA1,     // Oper
+    ClrA = 0xA2,     // ClearAgain
+    Crs = 0xA3,      // Crsel
+    Exs = 0xA4,      // Exsel
 }
-impl Kc {
-    pub fn get_macro_sequence(key: &Kc) -> Vec<Kc, 16> {
-        let mut vec: Vec<Kc, 16> = Vec::new();
-
-        match key {
-            Kc::MaCp => {
-                vec.push(Kc::ModCo).unwrap();
-                vec.push(Kc::C).unwrap();
-                vec
-            }
-            Kc::MaPa => {
-                vec.push(Kc::ModCo).unwrap();
-                vec.push(Kc::V).unwrap();
-                vec
-            }
-
-            Kc::MaRP => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::N0).unwrap();
-                vec
-            }
-            Kc::MaEx => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::N1).unwrap();
-                vec
-            }
-            Kc::MaAt => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::N2).unwrap();
-                vec
-            }
-            Kc::MaHs => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::N3).unwrap();
-                vec
-            }
-            Kc::MaDl => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::N4).unwrap();
-                vec
-            }
-            Kc::MaMd => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::N5).unwrap();
-                vec
-            }
-            Kc::MaCa => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::N6).unwrap();
-                vec
-            }
-            Kc::MaAmp => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::N7).unwrap();
-                vec
-            }
-            Kc::MaAst => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::N8).unwrap();
-                vec
-            }
-            Kc::MaLP => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::N9).unwrap();
-                vec
-            }
-            Kc::MaSL => {
-                vec.push(Kc::ModSu).unwrap();
-                vec.push(Kc::L).unwrap();
-                vec
-            }
-            Kc::MaLB => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::Lbrk).unwrap();
-                vec
-            }
-            Kc::MaRB => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::Rbrk).unwrap();
-                vec
-            }
-            Kc::MaPipe => {
-                vec.push(Kc::ModSh).unwrap();
-                vec.push(Kc::Bksl).unwrap();
-                vec
-            }
-            _ => vec,
-        }
-    }
 
-    /// Get combos information
-    pub fn get_combo(combo: &Kc) -> (Vec<Kc, 12>, Vec<Kc, 12>) {
-        let mut combo_vec: Vec<Kc, 12> = Vec::new();
-        let mut keys_to_change: Vec<Kc, 12> = Vec::new();
-
-        match combo {
-            Kc::ComboCtrlD => {
-                keys_to_change.push(Kc::ModCo).unwrap();
-                keys_to_change.push(Kc::D).unwrap();
-
-                combo_vec.push(Kc::ModCo).unwrap();
-                combo_vec.push(Kc::Bksp).unwrap();
-                (combo_vec, keys_to_change)
-            }
-            _ => (combo_vec, keys_to_change),
+impl HidKey {
+    /// Get the keycode of an ASCII character on the US layout, and if it needs shift
+    pub fn from_ascii(character: u8) -> Option<(HidKey, bool)> {
+        const LETTERS: [HidKey; 26] = [
+            HidKey::A,
+            HidKey::B,
+            HidKey::C,
+            HidKey::D,
+            HidKey::E,
+            HidKey::F,
+            HidKey::G,
+            HidKey::H,
+            HidKey::I,
+            HidKey::J,
+            HidKey::K,
+            HidKey::L,
+            HidKey::M,
+            HidKey::N,
+            HidKey::O,
+            HidKey::P,
+            HidKey::Q,
+            HidKey::R,
+            HidKey::S,
+            HidKey::T,
+            HidKey::U,
+            HidKey::V,
+            HidKey::W,
+            HidKey::X,
+            HidKey::Y,
+            HidKey::Z,
+        ];
+        const NUMBERS: [HidKey; 10] = [
+            HidKey::N0,
+            HidKey::N1,
+            HidKey::N2,
+            HidKey::N3,
+            HidKey::N4,
+            HidKey::N5,
+            HidKey::N6,
+            HidKey::N7,
+            HidKey::N8,
+            HidKey::N9,
+        ];
+
+        match character {
+            b'a'..=b'z' => Some((LETTERS[(character - b'a') as usize], false)),
+            b'A'..=b'Z' => Some((LETTERS[(character - b'A') as usize], true)),
+            b'0'..=b'9' => Some((NUMBERS[(character - b'0') as usize], false)),
+            b'!' => Some((HidKey::N1, true)),
+            b'@' => Some((HidKey::N2, true)),
+            b'#' => Some((HidKey::N3, true)),
+            b'$' => Some((HidKey::N4, true)),
+            b'%' => Some((HidKey::N5, true)),
+            b'^' => Some((HidKey::N6, true)),
+            b'&' => Some((HidKey::N7, true)),
+            b'*' => Some((HidKey::N8, true)),
+            b'(' => Some((HidKey::N9, true)),
+            b')' => Some((HidKey::N0, true)),
+            b'\n' => Some((HidKey::Entr, false)),
+            b'\t' => Some((HidKey::Tab, false)),
+            b' ' => Some((HidKey::Spac, false)),
+            b'-' => Some((HidKey::Mns, false)),
+            b'_' => Some((HidKey::Mns, true)),
+            b'=' => Some((HidKey::Eq, false)),
+            b'+' => Some((HidKey::Eq, true)),
+            b'[' => Some((HidKey::Lbrk, false)),
+            b'{' => Some((HidKey::Lbrk, true)),
+            b']' => Some((HidKey::Rbrk, false)),
+            b'}' => Some((HidKey::Rbrk, true)),
+            b'\\' => Some((HidKey::Bksl, false)),
+            b'|' => Some((HidKey::Bksl, true)),
+            b';' => Some((HidKey::Scn, false)),
+            b':' => Some((HidKey::Scn, true)),
+            b'\'' => Some((HidKey::Qte, false)),
+            b'"' => Some((HidKey::Qte, true)),
+            b'`' => Some((HidKey::Grav, false)),
+            b'~' => Some((HidKey::Grav, true)),
+            b',' => Some((HidKey::Com, false)),
+            b'<' => Some((HidKey::Com, true)),
+            b'.' => Some((HidKey::Per, false)),
+            b'>' => Some((HidKey::Per, true)),
+            b'/' => Some((HidKey::Fsl, false)),
+            b'?' => Some((HidKey::Fsl, true)),
+            _ => None,
         }
     }
 }
 
-pub enum KeyType {
-    Combo,
-    Macro,
-    Modifier,
-    Mouse,
-    Key,
-    Layer,
+/// Action of a key, stored in the keymap
+///
+/// The HID usages are only used when the reports are built,
+/// see `Kc` for the shorthands used in the layouts
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Action {
+    /// does nothing, blocks the lower layers
+    #[default]
+    No,
+    /// falls through to the lower active layers
+    Trns,
+    /// keyboard key
+    Key(HidKey),
+    /// modifier bits, held while the key is held
+    Modifier(u8),
+    /// keyboard key held with the modifier bits, see `S()`, `C()`, `A()`, `G()`
+    WithModifiers(HidKey, u8),
+    /// layer operation
+    Layer(LayerAction),
+    /// mouse control
+    Mouse(MouseAction),
+    /// media and consumer control
+    Consumer(ConsumerKey),
+    /// host power control
+    SystemControl(SystemControlKey),
+    /// switch between the 6-key and the n-key rollover keyboard report
+    Nkro(NkroAction),
+    /// macro sequence, played over several reports
+    ///
+    /// Steps with a wrapped key, e.g. `MacroStep::Tap(S(Kc::N9))`, are defined as a const item,
+    /// see `PARENS` in the dvorak 5x3 layout
+    Macro(&'static [MacroStep]),
+    /// tap for the action, hold for the modifier
+    ModTap(&'static Action, HidModifiers),
+    /// tap for the action, hold for the layer
+    LayerTap(usize, &'static Action),
+    /// modifier applied to the next key press only
+    OneShotModifier(HidModifiers),
+    /// layer applied to the next key press only
+    OneShotLayer(usize),
+    /// index of the action in the layout tap dance table
+    TapDance(usize),
+    /// the actions of a pressed combo, set by the combo engine
+    Combo(&'static [Action]),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LayerAction {
+    /// active while held
+    Momentary(usize),
+    /// toggled on release
+    Toggle(usize),
+    /// activates the layer, deactivates the others except the default
+    To(usize),
+    /// sets the default layer
+    Default(usize),
 }
 
-impl KeyType {
-    pub fn check_type(key: &Kc) -> KeyType {
-        match *key {
-            // return Macro key type
-            Kc::MaLP
-            | Kc::MaRP
-            | Kc::MaCp
-            | Kc::MaPa
-            | Kc::MaEx
-            | Kc::MaAt
-            | Kc::MaHs
-            | Kc::MaDl
-            | Kc::MaMd
-            | Kc::MaCa
-            | Kc::MaAmp
-            | Kc::MaAst
-            | Kc::MaSL
-            | Kc::MaLB
-            | Kc::MaRB
-            | Kc::MaPipe => KeyType::Macro,
-
-            // return Layer key type
-            Kc::L1 | Kc::L2 | Kc::L3 | Kc::L4 | Kc::L5 => KeyType::Layer,
-
-            // return Modifier key type
-            Kc::ModSh | Kc::ModCo | Kc::ModAl | Kc::ModSu => KeyType::Modifier,
-
-            // return Mouse key type
-            Kc::MoGL
-            | Kc::MoGD
-            | Kc::MoGU
-            | Kc::MoGR
-            | Kc::MoLC
-            | Kc::MoRC
-            | Kc::MoSL
-            | Kc::MoSR
-            | Kc::MoSU
-            | Kc::MoSD
-            | Kc::MoCF
-            | Kc::MoCN
-            | Kc::MoCS => KeyType::Mouse,
-
-            // return Combo key type
-            Kc::ComboCtrlD => KeyType::Combo,
-
-            _ => KeyType::Key,
-        }
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MouseAction {
+    GoLeft,
+    GoDown,
+    GoUp,
+    GoRight,
+    LeftClick,
+    RightClick,
+    MiddleClick,
+    BackClick,
+    ForwardClick,
+    /// keeps the button held until the drag lock key or the button is tapped again
+    DragLock(HidMouseKeys),
+    ScrollLeft,
+    ScrollRight,
+    ScrollUp,
+    ScrollDown,
+    CursorFast,
+    CursorNormal,
+    CursorSlow,
+    /// jumps the pointer to the cell of the 3x3 grid, 0 - 8 from the top left,
+    /// the next warp keys narrow the grid to that cell
+    Warp(u8),
+    /// starts the next warp from the whole screen again
+    WarpReset,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NkroAction {
+    On,
+    Off,
+    Toggle,
+}
+
+/// HID usage of the consumer page, sent in the media key report
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConsumerKey {
+    BrightnessUp = 0x006F,     // Display Brightness Increment
+    BrightnessDown = 0x0070,   // Display Brightness Decrement
+    FastForward = 0x00B3,      // Fast Forward
+    Rewind = 0x00B4,           // Rewind
+    NextTrack = 0x00B5,        // Scan Next Track
+    PreviousTrack = 0x00B6,    // Scan Previous Track
+    Stop = 0x00B7,             // Stop
+    Eject = 0x00B8,            // Eject
+    PlayPause = 0x00CD,        // Play/Pause
+    Mute = 0x00E2,             // Mute
+    VolumeUp = 0x00E9,         // Volume Increment
+    VolumeDown = 0x00EA,       // Volume Decrement
+    MediaSelect = 0x0183,      // AL Consumer Control Configuration
+    Mail = 0x018A,             // AL Email Reader
+    Calculator = 0x0192,       // AL Calculator
+    MyComputer = 0x0194,       // AL Local Machine Browser
+    BrowserSearch = 0x0221,    // AC Search
+    BrowserHome = 0x0223,      // AC Home
+    BrowserBack = 0x0224,      // AC Back
+    BrowserForward = 0x0225,   // AC Forward
+    BrowserStop = 0x0226,      // AC Stop
+    BrowserRefresh = 0x0227,   // AC Refresh
+    BrowserFavorites = 0x022A, // AC Bookmarks
+}
+
+/// HID usage of the generic desktop system controls, sent in the system control report
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SystemControlKey {
+    PowerDown = 0x81, // System Power Down
+    Sleep = 0x82,     // System Sleep
+    WakeUp = 0x83,    // System Wake Up
+}
+
+/// Keycode shorthands for the layouts
+pub struct Kc;
+
+#[allow(non_upper_case_globals)]
+impl Kc {
+    pub const None: Action = Action::Key(HidKey::None);
+    pub const Trns: Action = Action::Trns; // Transparent: falls through to the lower active layers
+    pub const No: Action = Action::No; // NoOperation: does nothing, blocks the lower layers
+    pub const Undf: Action = Action::No; // Undefined
+    pub const A: Action = Action::Key(HidKey::A);
+    pub const B: Action = Action::Key(HidKey::B);
+    pub const C: Action = Action::Key(HidKey::C);
+    pub const D: Action = Action::Key(HidKey::D);
+    pub const E: Action = Action::Key(HidKey::E);
+    pub const F: Action = Action::Key(HidKey::F);
+    pub const G: Action = Action::Key(HidKey::G);
+    pub const H: Action = Action::Key(HidKey::H);
+    pub const I: Action = Action::Key(HidKey::I);
+    pub const J: Action = Action::Key(HidKey::J);
+    pub const K: Action = Action::Key(HidKey::K);
+    pub const L: Action = Action::Key(HidKey::L);
+    pub const M: Action = Action::Key(HidKey::M);
+    pub const N: Action = Action::Key(HidKey::N);
+    pub const O: Action = Action::Key(HidKey::O);
+    pub const P: Action = Action::Key(HidKey::P);
+    pub const Q: Action = Action::Key(HidKey::Q);
+    pub const R: Action = Action::Key(HidKey::R);
+    pub const S: Action = Action::Key(HidKey::S);
+    pub const T: Action = Action::Key(HidKey::T);
+    pub const U: Action = Action::Key(HidKey::U);
+    pub const V: Action = Action::Key(HidKey::V);
+    pub const W: Action = Action::Key(HidKey::W);
+    pub const X: Action = Action::Key(HidKey::X);
+    pub const Y: Action = Action::Key(HidKey::Y);
+    pub const Z: Action = Action::Key(HidKey::Z);
+    pub const N1: Action = Action::Key(HidKey::N1);
+    pub const N2: Action = Action::Key(HidKey::N2);
+    pub const N3: Action = Action::Key(HidKey::N3);
+    pub const N4: Action = Action::Key(HidKey::N4);
+    pub const N5: Action = Action::Key(HidKey::N5);
+    pub const N6: Action = Action::Key(HidKey::N6);
+    pub const N7: Action = Action::Key(HidKey::N7);
+    pub const N8: Action = Action::Key(HidKey::N8);
+    pub const N9: Action = Action::Key(HidKey::N9);
+    pub const N0: Action = Action::Key(HidKey::N0);
+    pub const Entr: Action = Action::Key(HidKey::Entr);
+    pub const Esc: Action = Action::Key(HidKey::Esc);
+    pub const Bksp: Action = Action::Key(HidKey::Bksp);
+    pub const Tab: Action = Action::Key(HidKey::Tab);
+    pub const Spac: Action = Action::Key(HidKey::Spac);
+    pub const Mns: Action = Action::Key(HidKey::Mns);
+    pub const Eq: Action = Action::Key(HidKey::Eq);
+    pub const Lbrk: Action = Action::Key(HidKey::Lbrk);
+    pub const Rbrk: Action = Action::Key(HidKey::Rbrk);
+    pub const Bksl: Action = Action::Key(HidKey::Bksl);
+    pub const Nsh: Action = Action::Key(HidKey::Nsh);
+    pub const Scn: Action = Action::Key(HidKey::Scn);
+    pub const Qte: Action = Action::Key(HidKey::Qte);
+    pub const Grav: Action = Action::Key(HidKey::Grav);
+    pub const Com: Action = Action::Key(HidKey::Com);
+    pub const Per: Action = Action::Key(HidKey::Per);
+    pub const Fsl: Action = Action::Key(HidKey::Fsl);
+    pub const Caps: Action = Action::Key(HidKey::Caps);
+    pub const F1: Action = Action::Key(HidKey::F1);
+    pub const F2: Action = Action::Key(HidKey::F2);
+    pub const F3: Action = Action::Key(HidKey::F3);
+    pub const F4: Action = Action::Key(HidKey::F4);
+    pub const F5: Action = Action::Key(HidKey::F5);
+    pub const F6: Action = Action::Key(HidKey::F6);
+    pub const F7: Action = Action::Key(HidKey::F7);
+    pub const F8: Action = Action::Key(HidKey::F8);
+    pub const F9: Action = Action::Key(HidKey::F9);
+    pub const F10: Action = Action::Key(HidKey::F10);
+    pub const F11: Action = Action::Key(HidKey::F11);
+    pub const F12: Action = Action::Key(HidKey::F12);
+    pub const Pscr: Action = Action::Key(HidKey::Pscr);
+    pub const Scll: Action = Action::Key(HidKey::Scll);
+    pub const Pse: Action = Action::Key(HidKey::Pse);
+    pub const Ins: Action = Action::Key(HidKey::Ins);
+    pub const Home: Action = Action::Key(HidKey::Home);
+    pub const Pgup: Action = Action::Key(HidKey::Pgup);
+    pub const Del: Action = Action::Key(HidKey::Del);
+    pub const End: Action = Action::Key(HidKey::End);
+    pub const Pgdn: Action = Action::Key(HidKey::Pgdn);
+    pub const ArR: Action = Action::Key(HidKey::ArR);
+    pub const ArL: Action = Action::Key(HidKey::ArL);
+    pub const ArD: Action = Action::Key(HidKey::ArD);
+    pub const ArU: Action = Action::Key(HidKey::ArU);
+    pub const Nlk: Action = Action::Key(HidKey::Nlk);
+    pub const KpS: Action = Action::Key(HidKey::KpS);
+    pub const KpA: Action = Action::Key(HidKey::KpA);
+    pub const KpM: Action = Action::Key(HidKey::KpM);
+    pub const KpP: Action = Action::Key(HidKey::KpP);
+    pub const KpE: Action = Action::Key(HidKey::KpE);
+    pub const Kp1: Action = Action::Key(HidKey::Kp1);
+    pub const Kp2: Action = Action::Key(HidKey::Kp2);
+    pub const Kp3: Action = Action::Key(HidKey::Kp3);
+    pub const Kp4: Action = Action::Key(HidKey::Kp4);
+    pub const Kp5: Action = Action::Key(HidKey::Kp5);
+    pub const Kp6: Action = Action::Key(HidKey::Kp6);
+    pub const Kp7: Action = Action::Key(HidKey::Kp7);
+    pub const Kp8: Action = Action::Key(HidKey::Kp8);
+    pub const Kp9: Action = Action::Key(HidKey::Kp9);
+    pub const Kp0: Action = Action::Key(HidKey::Kp0);
+    pub const KpD: Action = Action::Key(HidKey::KpD);
+    pub const Nbl: Action = Action::Key(HidKey::Nbl);
+    pub const App: Action = Action::Key(HidKey::App);
+    pub const Pwr: Action = Action::Key(HidKey::Pwr);
+    pub const KpEql: Action = Action::Key(HidKey::KpEql);
+    pub const F13: Action = Action::Key(HidKey::F13);
+    pub const F14: Action = Action::Key(HidKey::F14);
+    pub const F15: Action = Action::Key(HidKey::F15);
+    pub const F16: Action = Action::Key(HidKey::F16);
+    pub const F17: Action = Action::Key(HidKey::F17);
+    pub const F18: Action = Action::Key(HidKey::F18);
+    pub const F19: Action = Action::Key(HidKey::F19);
+    pub const F20: Action = Action::Key(HidKey::F20);
+    pub const F21: Action = Action::Key(HidKey::F21);
+    pub const F22: Action = Action::Key(HidKey::F22);
+    pub const F23: Action = Action::Key(HidKey::F23);
+    pub const F24: Action = Action::Key(HidKey::F24);
+    pub const Exe: Action = Action::Key(HidKey::Exe);
+    pub const Help: Action = Action::Key(HidKey::Help);
+    pub const Menu: Action = Action::Key(HidKey::Menu);
+    pub const Sel: Action = Action::Key(HidKey::Sel);
+    pub const Stp: Action = Action::Key(HidKey::Stp);
+    pub const Agn: Action = Action::Key(HidKey::Agn);
+    pub const Und: Action = Action::Key(HidKey::Und);
+    pub const Cut: Action = Action::Key(HidKey::Cut);
+    pub const Cop: Action = Action::Key(HidKey::Cop);
+    pub const Pas: Action = Action::Key(HidKey::Pas);
+    pub const Fin: Action = Action::Key(HidKey::Fin);
+    pub const Mute: Action = Action::Key(HidKey::Mute);
+    pub const Vup: Action = Action::Key(HidKey::Vup);
+    pub const Vdown: Action = Action::Key(HidKey::Vdown);
+    pub const LckC: Action = Action::Key(HidKey::LckC);
+    pub const LckN: Action = Action::Key(HidKey::LckN);
+    pub const LckS: Action = Action::Key(HidKey::LckS);
+    pub const KpC: Action = Action::Key(HidKey::KpC);
+    pub const KpEql400: Action = Action::Key(HidKey::KpEql400);
+    pub const Int1: Action = Action::Key(HidKey::Int1);
+    pub const Int2: Action = Action::Key(HidKey::Int2);
+    pub const Int3: Action = Action::Key(HidKey::Int3);
+    pub const Int4: Action = Action::Key(HidKey::Int4);
+    pub const Int5: Action = Action::Key(HidKey::Int5);
+    pub const Int6: Action = Action::Key(HidKey::Int6);
+    pub const Int7: Action = Action::Key(HidKey::Int7);
+    pub const Int8: Action = Action::Key(HidKey::Int8);
+    pub const Int9: Action = Action::Key(HidKey::Int9);
+    pub const Lg1: Action = Action::Key(HidKey::Lg1);
+    pub const Lg3: Action = Action::Key(HidKey::Lg3);
+    pub const Lg4: Action = Action::Key(HidKey::Lg4);
+    pub const Lg5: Action = Action::Key(HidKey::Lg5);
+    pub const Lg6: Action = Action::Key(HidKey::Lg6);
+    pub const Lg7: Action = Action::Key(HidKey::Lg7);
+    pub const Lg8: Action = Action::Key(HidKey::Lg8);
+    pub const Lg9: Action = Action::Key(HidKey::Lg9);
+    pub const AltE: Action = Action::Key(HidKey::AltE);
+    pub const Sys: Action = Action::Key(HidKey::Sys);
+    pub const Canc: Action = Action::Key(HidKey::Canc);
+    pub const Clr: Action = Action::Key(HidKey::Clr);
+    pub const Pri: Action = Action::Key(HidKey::Pri);
+    pub const Ret: Action = Action::Key(HidKey::Ret);
+    pub const Sep: Action = Action::Key(HidKey::Sep);
+    pub const Out: Action = Action::Key(HidKey::Out);
+    pub const Oper: Action = Action::Key(HidKey::Oper);
+    pub const ClrA: Action = Action::Key(HidKey::ClrA);
+    pub const Crs: Action = Action::Key(HidKey::Crs);
+    pub const Exs: Action = Action::Key(HidKey::Exs);
+
+    // layers
+    pub const L1: Action = Action::Layer(LayerAction::Momentary(1)); // Layer1
+    pub const L2: Action = Action::Layer(LayerAction::Momentary(2)); // Layer2
+    pub const L3: Action = Action::Layer(LayerAction::Momentary(3)); // Layer3
+    pub const L4: Action = Action::Layer(LayerAction::Momentary(4)); // Layer4
+    pub const L5: Action = Action::Layer(LayerAction::Momentary(5)); // Layer5
+
+    // modifiers
+    pub const ModSh: Action = Action::Modifier(HidModifiers::Shift as u8); // ModifierShift
+    pub const ModCo: Action = Action::Modifier(HidModifiers::Control as u8); // ModifierControl
+    pub const ModAl: Action = Action::Modifier(HidModifiers::Alt as u8); // ModifierAlt
+    pub const ModSu: Action = Action::Modifier(HidModifiers::Super as u8); // ModifierSuper
+    pub const LCtl: Action = Action::Modifier(HidModifiers::Control as u8); // LeftControl
+    pub const LSft: Action = Action::Modifier(HidModifiers::Shift as u8); // LeftShift
+    pub const LAlt: Action = Action::Modifier(HidModifiers::Alt as u8); // LeftAlt
+    pub const LGui: Action = Action::Modifier(HidModifiers::Super as u8); // LeftGui
+    pub const RCtl: Action = Action::Modifier(HidModifiers::RightControl as u8); // RightControl
+    pub const RSft: Action = Action::Modifier(HidModifiers::RightShift as u8); // RightShift
+    pub const RAlt: Action = Action::Modifier(HidModifiers::RightAlt as u8); // RightAlt (AltGr)
+    pub const RGui: Action = Action::Modifier(HidModifiers::RightSuper as u8); // RightGui
+
+    // mouse controls
+    pub const MoGL: Action = Action::Mouse(MouseAction::GoLeft); // MouseGoLeft
+    pub const MoGD: Action = Action::Mouse(MouseAction::GoDown); // MouseGoDown
+    pub const MoGU: Action = Action::Mouse(MouseAction::GoUp); // MouseGoUp
+    pub const MoGR: Action = Action::Mouse(MouseAction::GoRight); // MouseGoRight
+    pub const MoLC: Action = Action::Mouse(MouseAction::LeftClick); // MouseLeftClick
+    pub const MoRC: Action = Action::Mouse(MouseAction::RightClick); // MouseRightClick
+    pub const MoMC: Action = Action::Mouse(MouseAction::MiddleClick); // MouseMiddleClick
+    pub const MoBC: Action = Action::Mouse(MouseAction::BackClick); // MouseBackClick
+    pub const MoFC: Action = Action::Mouse(MouseAction::ForwardClick); // MouseForwardClick
+    pub const MoDL: Action = Action::Mouse(MouseAction::DragLock(HidMouseKeys::LeftClick)); // MouseDragLock
+    pub const MoSL: Action = Action::Mouse(MouseAction::ScrollLeft); // MouseScrollLeft
+    pub const MoSR: Action = Action::Mouse(MouseAction::ScrollRight); // MouseScrollRight
+    pub const MoSU: Action = Action::Mouse(MouseAction::ScrollUp); // MouseScrollUp
+    pub const MoSD: Action = Action::Mouse(MouseAction::ScrollDown); // MouseScrollDown
+    pub const MoCF: Action = Action::Mouse(MouseAction::CursorFast); // MouseCursorFast
+    pub const MoCN: Action = Action::Mouse(MouseAction::CursorNormal); // MouseCursorNormal
+    pub const MoCS: Action = Action::Mouse(MouseAction::CursorSlow); // MouseCursorSlow
+    pub const MoW1: Action = Action::Mouse(MouseAction::Warp(0)); // MouseWarpTopLeft
+    pub const MoW2: Action = Action::Mouse(MouseAction::Warp(1)); // MouseWarpTop
+    pub const MoW3: Action = Action::Mouse(MouseAction::Warp(2)); // MouseWarpTopRight
+    pub const MoW4: Action = Action::Mouse(MouseAction::Warp(3)); // MouseWarpLeft
+    pub const MoW5: Action = Action::Mouse(MouseAction::Warp(4)); // MouseWarpCenter
+    pub const MoW6: Action = Action::Mouse(MouseAction::Warp(5)); // MouseWarpRight
+    pub const MoW7: Action = Action::Mouse(MouseAction::Warp(6)); // MouseWarpBottomLeft
+    pub const MoW8: Action = Action::Mouse(MouseAction::Warp(7)); // MouseWarpBottom
+    pub const MoW9: Action = Action::Mouse(MouseAction::Warp(8)); // MouseWarpBottomRight
+    pub const MoWR: Action = Action::Mouse(MouseAction::WarpReset); // MouseWarpReset
+
+    // media and consumer controls
+    pub const MPly: Action = Action::Consumer(ConsumerKey::PlayPause); // MediaPlayPause
+    pub const MNxt: Action = Action::Consumer(ConsumerKey::NextTrack); // MediaNextTrack
+    pub const MPrv: Action = Action::Consumer(ConsumerKey::PreviousTrack); // MediaPreviousTrack
+    pub const MStp: Action = Action::Consumer(ConsumerKey::Stop); // MediaStop
+    pub const MFfd: Action = Action::Consumer(ConsumerKey::FastForward); // MediaFastForward
+    pub const MRwd: Action = Action::Consumer(ConsumerKey::Rewind); // MediaRewind
+    pub const MEjt: Action = Action::Consumer(ConsumerKey::Eject); // MediaEject
+    pub const MSel: Action = Action::Consumer(ConsumerKey::MediaSelect); // MediaSelect
+    pub const MMut: Action = Action::Consumer(ConsumerKey::Mute); // MediaMute
+    pub const MVup: Action = Action::Consumer(ConsumerKey::VolumeUp); // MediaVolumeUp
+    pub const MVdn: Action = Action::Consumer(ConsumerKey::VolumeDown); // MediaVolumeDown
+    pub const BriU: Action = Action::Consumer(ConsumerKey::BrightnessUp); // BrightnessUp
+    pub const BriD: Action = Action::Consumer(ConsumerKey::BrightnessDown); // BrightnessDown
+    pub const Mail: Action = Action::Consumer(ConsumerKey::Mail); // Mail
+    pub const Calc: Action = Action::Consumer(ConsumerKey::Calculator); // Calculator
+    pub const MyPc: Action = Action::Consumer(ConsumerKey::MyComputer); // MyComputer
+    pub const WSch: Action = Action::Consumer(ConsumerKey::BrowserSearch); // BrowserSearch
+    pub const WHom: Action = Action::Consumer(ConsumerKey::BrowserHome); // BrowserHome
+    pub const WBck: Action = Action::Consumer(ConsumerKey::BrowserBack); // BrowserBack
+    pub const WFwd: Action = Action::Consumer(ConsumerKey::BrowserForward); // BrowserForward
+    pub const WStp: Action = Action::Consumer(ConsumerKey::BrowserStop); // BrowserStop
+    pub const WRef: Action = Action::Consumer(ConsumerKey::BrowserRefresh); // BrowserRefresh
+    pub const WFav: Action = Action::Consumer(ConsumerKey::BrowserFavorites); // BrowserFavorites
+
+    // system controls
+    pub const SPwr: Action = Action::SystemControl(SystemControlKey::PowerDown); // SystemPowerDown
+    pub const SSlp: Action = Action::SystemControl(SystemControlKey::Sleep); // SystemSleep
+    pub const SWak: Action = Action::SystemControl(SystemControlKey::WakeUp); // SystemWakeUp
+
+    // keyboard report
+    pub const NkOn: Action = Action::Nkro(NkroAction::On); // NkroOn
+    pub const NkOf: Action = Action::Nkro(NkroAction::Off); // NkroOff
+    pub const NkTg: Action = Action::Nkro(NkroAction::Toggle); // NkroToggle
+}
+
+#[allow(non_snake_case)]
+impl Kc {
+    /// MomentaryLayer: active while held
+    pub const fn MO(layer: usize) -> Action {
+        Action::Layer(LayerAction::Momentary(layer))
+    }
+
+    /// ToggleLayer: toggled on release
+    pub const fn TG(layer: usize) -> Action {
+        Action::Layer(LayerAction::Toggle(layer))
+    }
+
+    /// ToLayer: activates the layer, deactivates the others except the default
+    pub const fn TO(layer: usize) -> Action {
+        Action::Layer(LayerAction::To(layer))
     }
+
+    /// DefaultLayer: sets the default layer
+    pub const fn DF(layer: usize) -> Action {
+        Action::Layer(LayerAction::Default(layer))
+    }
+
+    /// ModTap: tap for the key, hold for the modifier
+    /// a wrapped key is referenced through a const item, see `with_modifiers`
+    pub const fn MT(key: &'static Action, modifier: HidModifiers) -> Action {
+        Action::ModTap(key, modifier)
+    }
+
+    /// LayerTap: tap for the key, hold for the layer
+    /// a wrapped key is referenced through a const item, see `with_modifiers`
+    pub const fn LT(layer: usize, key: &'static Action) -> Action {
+        Action::LayerTap(layer, key)
+    }
+
+    /// OneShotModifier: applies to the next key press only
+    pub const fn OSM(modifier: HidModifiers) -> Action {
+        Action::OneShotModifier(modifier)
+    }
+
+    /// OneShotLayer: applies to the next key press only
+    pub const fn OSL(layer: usize) -> Action {
+        Action::OneShotLayer(layer)
+    }
+
+    /// TapDance: index of the action in the layout tap dance table
+    pub const fn TD(index: usize) -> Action {
+        Action::TapDance(index)
+    }
+}
+
+/// Step of a macro sequence, every key press or release is sent in its own report
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MacroStep {
+    /// press the key (key or modifier)
+    Press(Action),
+    /// release the key
+    Release(Action),
+    /// press and release the key
+    Tap(Action),
+    /// wait for the number of milliseconds
+    Delay(u64),
+    /// type the ASCII text
+    Text(&'static str),
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum HidModifiers {
     None = 0x00,
     Control = 0x01,
@@ -392,20 +715,123 @@ pub enum HidModifiers {
     Shift = 0x02,
     Alt = 0x04,
     Super = 0x08,
+    Meh = 0x07,
+    Hyper = 0x0F,
+    RightControl = 0x10,
+    RightShift = 0x20,
+    RightAlt = 0x40,
+    RightSuper = 0x80,
 }
-impl HidModifiers {
-    pub fn get_modifier(key: &Kc) -> u8 {
-        // set the modifier
-        match *key {
-            Kc::ModSh => HidModifiers::Shift as u8,
-            Kc::ModCo => HidModifiers::Control as u8,
-            Kc::ModAl => HidModifiers::Alt as u8,
-            Kc::ModSu => HidModifiers::Super as u8,
-            _ => 0,
+
+/// Wrap the key with the modifiers, they are held only while the key is held
+///
+/// Wrapping an already wrapped key adds the modifiers, e.g. `C(S(Kc::T))`,
+/// wrapping `Kc::None` gives a key holding only the modifiers, e.g. `HYPR(Kc::None)`.
+/// Only keys and modifiers can be wrapped, wrapping any other action panics,
+/// in a const item it fails to compile:
+///
+/// ```compile_fail,E0080
+/// use esp32_rustboard::config::enums::*;
+///
+/// const SHIFTED_LAYER: Action = S(Kc::MO(1));
+/// ```
+///
+/// A wrapped key is a call, it is not promoted to a static inside the non-const `layout()` fn.
+/// It is used in the keymap as is, but a `&'static` reference to it, as taken by a mod-tap,
+/// a layer-tap, a macro, a tap dance or a combo action, is made to a const item:
+///
+/// ```
+/// use esp32_rustboard::config::enums::*;
+///
+/// const COLON: Action = S(Kc::Scn);
+///
+/// fn layout_key() -> Action {
+///     Kc::MT(&COLON, HidModifiers::Control)
+/// }
+/// # assert_eq!(layout_key(), Action::ModTap(&COLON, HidModifiers::Control));
+/// ```
+///
+/// ```compile_fail,E0716
+/// use esp32_rustboard::config::enums::*;
+///
+/// fn layout_key() -> Action {
+///     // temporary value dropped while borrowed
+///     Kc::MT(&S(Kc::Scn), HidModifiers::Control)
+/// }
+/// # let _ = layout_key();
+/// ```
+pub const fn with_modifiers(key: Action, modifiers: u8) -> Action {
+    match key {
+        Action::Key(hid_key) => Action::WithModifiers(hid_key, modifiers),
+        Action::WithModifiers(hid_key, key_modifiers) => {
+            Action::WithModifiers(hid_key, key_modifiers | modifiers)
         }
+        Action::Modifier(key_modifiers) => Action::Modifier(key_modifiers | modifiers),
+        _ => panic!("only keys and modifiers can be wrapped"),
     }
 }
 
+/// Shift + key
+#[allow(non_snake_case)]
+pub const fn S(key: Action) -> Action {
+    with_modifiers(key, HidModifiers::Shift as u8)
+}
+
+/// Control + key
+#[allow(non_snake_case)]
+pub const fn C(key: Action) -> Action {
+    with_modifiers(key, HidModifiers::Control as u8)
+}
+
+/// Alt + key
+#[allow(non_snake_case)]
+pub const fn A(key: Action) -> Action {
+    with_modifiers(key, HidModifiers::Alt as u8)
+}
+
+/// Super (GUI) + key
+#[allow(non_snake_case)]
+pub const fn G(key: Action) -> Action {
+    with_modifiers(key, HidModifiers::Super as u8)
+}
+
+/// Right Shift + key
+#[allow(non_snake_case)]
+pub const fn RS(key: Action) -> Action {
+    with_modifiers(key, HidModifiers::RightShift as u8)
+}
+
+/// Right Control + key
+#[allow(non_snake_case)]
+pub const fn RC(key: Action) -> Action {
+    with_modifiers(key, HidModifiers::RightControl as u8)
+}
+
+/// Right Alt (AltGr) + key
+#[allow(non_snake_case)]
+pub const fn RA(key: Action) -> Action {
+    with_modifiers(key, HidModifiers::RightAlt as u8)
+}
+
+/// Right Super (GUI) + key
+#[allow(non_snake_case)]
+pub const fn RG(key: Action) -> Action {
+    with_modifiers(key, HidModifiers::RightSuper as u8)
+}
+
+/// Meh: Control + Shift + Alt + key
+#[allow(non_snake_case)]
+pub const fn MEH(key: Action) -> Action {
+    with_modifiers(key, HidModifiers::Meh as u8)
+}
+
+/// Hyper: Control + Shift + Alt + Super + key
+#[allow(non_snake_case)]
+pub const fn HYPR(key: Action) -> Action {
+    with_modifiers(key, HidModifiers::Hyper as u8)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum HidMouseKeys {
     LeftClick = 0x01,
     RightClick = 0x02,
@@ -1,48 +1,32 @@
-use crate::{
-    config::{enums::*, layout::*},
-    matrix::{KeyPos, PinMatrix},
-};
-use esp_idf_hal::{
-    gpio::{IOPin, PinDriver},
-    prelude::Peripherals,
+use crate::config::{enums::*, layout::*};
+
+#[cfg(target_os = "espidf")]
+use {
+    crate::matrix::esp::EspPinMatrix,
+    esp_idf_hal::{gpio::IOPin, prelude::Peripherals},
 };
 
-pub fn provide_pin_matrix() -> PinMatrix<'static> {
+#[cfg(target_os = "espidf")]
+pub fn provide_pin_matrix() -> EspPinMatrix {
     let peripherals = Peripherals::take().expect("Not able to init peripherals.");
 
     let rows = [
-        PinDriver::output(peripherals.pins.gpio12.downgrade())
-            .expect("Not able to set port as output."),
-        PinDriver::output(peripherals.pins.gpio18.downgrade())
-            .expect("Not able to set port as output."),
-        PinDriver::output(peripherals.pins.gpio19.downgrade())
-            .expect("Not able to set port as output."),
-        PinDriver::output(peripherals.pins.gpio20.downgrade())
-            .expect("Not able to set port as output."),
+        peripherals.pins.gpio12.downgrade(),
+        peripherals.pins.gpio18.downgrade(),
+        peripherals.pins.gpio19.downgrade(),
+        peripherals.pins.gpio20.downgrade(),
     ];
 
     let cols = [
-        PinDriver::input(peripherals.pins.gpio4.downgrade())
-            .expect("Not able to set port as input."),
-        PinDriver::input(peripherals.pins.gpio5.downgrade())
-            .expect("Not able to set port as input."),
-        PinDriver::input(peripherals.pins.gpio7.downgrade())
-            .expect("Not able to set port as input."),
-        PinDriver::input(peripherals.pins.gpio6.downgrade())
-            .expect("Not able to set port as input."),
-        PinDriver::input(peripherals.pins.gpio10.downgrade())
-            .expect("Not able to set port as input."),
-        PinDriver::input(peripherals.pins.gpio3.downgrade())
-            .expect("Not able to set port as input."),
+        peripherals.pins.gpio4.downgrade(),
+        peripherals.pins.gpio5.downgrade(),
+        peripherals.pins.gpio7.downgrade(),
+        peripherals.pins.gpio6.downgrade(),
+        peripherals.pins.gpio10.downgrade(),
+        peripherals.pins.gpio3.downgrade(),
     ];
 
-    let registered_local_keys_array = [KeyPos::default(); 6];
-
-    PinMatrix {
-        rows,
-        cols,
-        registered_local_keys_array,
-    }
+    EspPinMatrix::from_gpios(rows, cols)
 }
 //*********************************************************************************************
 // LAYER 0:
@@ -51,8 +35,9 @@ pub fn provide_pin_matrix() -> PinMatrix<'static> {
 //   0 |_____|__'__|__,__|__.__|__p__|__y__|              0 |__f__|__g__|__c__|__r__|__l__|_____|
 //   1 |_____|__a__|__o__|__e__|__u__|__i__|              1 |__d__|__h__|__t__|__n__|__s__|_____|
 //   2 |_____|_CTL_|__q__|__j__|__k__|__x__|              2 |__b__|__m__|__w__|__v__|__z__|_____|
-//   3                   |_SUP_|SPACE|SHIFT|              3 |_TAB_|ENTER|LYR_1|
+//   3                   |_SUP_|SPACE|SHIFT|              3 |_TAB_|ENTER|BSP/1|
 //
+// BSP/1: backspace on tap, layer 1 on hold
 //*********************************************************************************************
 // LAYER 1:
 //
@@ -60,9 +45,20 @@ pub fn provide_pin_matrix() -> PinMatrix<'static> {
 //   0 |_____|_ESC_|__7__|__8__|__9__|_PScr|              0 |_SLCK|__(__|__)__|__=__|__/__|_____|
 //   1 |_____|_BSP_|__4__|__5__|__6__|_DEL_|              1 |__-__|_left|_down|__up_|right|_____|
 //   2 |_____|__0__|__1__|__2__|__3__|_ALT_|              2 |__\__|__[__|__]__|__`__|__;__|_____|
-//   3                   |_SUP_|SPACE|SHIFT|              3 |_TAB_|ENTER|LYR_1|
+//   3                   |_SUP_|SPACE|SHIFT|              3 |_TAB_|ENTER|BSP/1|
 //
 //*********************************************************************************************
+// the macros, tap dances and combo actions are `&'static` slices,
+// a wrapped key like `S(Kc::N9)` is a call, which is not promoted to a static inside `layout()` (E0716),
+// so the slices holding one are const items
+
+/// Types `()` and moves the cursor between the parens
+const PARENS: &[MacroStep] = &[
+    MacroStep::Tap(S(Kc::N9)),
+    MacroStep::Tap(S(Kc::N0)),
+    MacroStep::Tap(Kc::ArL),
+];
+
 #[rustfmt::skip]
 pub fn layout() -> Layout {
     Layout { keymap: 
@@ -76,24 +72,40 @@ pub fn layout() -> Layout {
                 /*               +-------------+--------------+--------------+--------------+-------------+--------------+        +------------+--------------+------------+-------------+-------------+------------+*/
                 /*  ROW 2  */ [/*|*/Kc::Undf,/*|*/Kc::ModCo,/*|*/ Kc::Q,   /*|*/Kc::J,    /*|*/Kc::K,   /*|*/Kc::X,    /*|        |*/Kc::B,  /*|*/ Kc::M,   /*|*/ Kc::W, /*|*/Kc::V,   /*|*/Kc::Z,   /*|*/Kc::Undf/*|*/],
                 /*               +-------------+--------------+--------------+--------------+-------------+--------------+        +------------+--------------+------------+-------------+-------------+------------+*/
-                /*  ROW 3  */ [/*|*/Kc::Undf,/*|*/Kc::Undf, /*|*/ Kc::Undf,/*|*/Kc::ModAl,/*|*/Kc::Spac,/*|*/Kc::ModSh,/*|        |*/Kc::Tab,/*|*/ Kc::Entr,/*|*/ Kc::L1,/*|*/Kc::Undf,/*|*/Kc::Undf,/*|*/Kc::Undf/*|*/],
+                /*  ROW 3  */ [/*|*/Kc::Undf,/*|*/Kc::Undf, /*|*/ Kc::Undf,/*|*/Kc::ModAl,/*|*/Kc::Spac,/*|*/Kc::ModSh,/*|        |*/Kc::Tab,/*|*/ Kc::Entr,/*|*/ Kc::LT(1, &Kc::Bksp),/*|*/Kc::Undf,/*|*/Kc::Undf,/*|*/Kc::Undf/*|*/],
                 /*               +-------------+--------------+--------------+--------------+-------------+--------------+        +------------+--------------+------------+-------------+-------------+------------+*/
 
             ],
             [
                 /* LAYER 1 */  /*    COL 0          COL 1          COL 2          COL 3          COL 4         COL 5                     COL 6         COL 7         COL 8         COL 9         COL 10        COL 11     */
                 /*               +-------------+---------------+--------------+--------------+-------------+--------------+          +-------------+--------------+-------------+-------------+-------------+------------+*/
-                /*  ROW 0  */ [/*|*/Kc::Undf,/*|*/Kc::Esc,   /*|*/Kc::N7,   /*|*/Kc::N8,   /*|*/Kc::N9,  /*|*/Kc::Pscr, /*|          |*/Kc::MaSL,/*|*/Kc::MaLP, /*|*/Kc::MaRP,/*|*/Kc::Eq,  /*|*/Kc::Fsl, /*|*/Kc::Undf/*|*/],
+                /*  ROW 0  */ [/*|*/Kc::Undf,/*|*/Kc::Esc,   /*|*/Kc::N7,   /*|*/Kc::N8,   /*|*/Kc::N9,  /*|*/Kc::Pscr, /*|          |*/G(Kc::L),/*|*/S(Kc::N9),/*|*/S(Kc::N0),/*|*/Kc::Eq,  /*|*/Kc::Fsl, /*|*/Kc::Undf/*|*/],
                 /*               +-------------+---------------+--------------+--------------+-------------+--------------+          +-------------+--------------+-------------+-------------+-------------+------------+*/
                 /*  ROW 1  */ [/*|*/Kc::Undf,/*|*/Kc::Bksp,  /*|*/Kc::N4,   /*|*/Kc::N5,   /*|*/Kc::N6,  /*|*/Kc::Del,  /*|          |*/Kc::Mns, /*|*/Kc::ArL,  /*|*/Kc::ArD, /*|*/Kc::ArU, /*|*/Kc::ArR, /*|*/Kc::Undf/*|*/],
                 /*               +-------------+---------------+--------------+--------------+-------------+--------------+          +-------------+--------------+-------------+-------------+-------------+------------+*/
                 /*  ROW 2  */ [/*|*/Kc::Undf,/*|*/Kc::N0,    /*|*/Kc::N1,   /*|*/Kc::N2,   /*|*/Kc::N3,  /*|*/Kc::ModSu,/*|          |*/Kc::Bksl,/*|*/Kc::Lbrk, /*|*/Kc::Rbrk,/*|*/Kc::Grav,/*|*/Kc::Scn, /*|*/Kc::Undf/*|*/],
                 /*               +-------------+---------------+--------------+--------------+-------------+--------------+          +-------------+--------------+-------------+-------------+-------------+------------+*/
-                /*  ROW 3  */ [/*|*/Kc::Undf,/*|*/Kc::Undf,  /*|*/Kc::Undf, /*|*/Kc::ModAl,/*|*/Kc::Spac,/*|*/Kc::ModSh,/*|          |*/Kc::Tab, /*|*/Kc::Entr, /*|*/Kc::L1,  /*|*/Kc::Undf,/*|*/Kc::Undf,/*|*/Kc::Undf/*|*/],
+                /*  ROW 3  */ [/*|*/Kc::Undf,/*|*/Kc::Undf,  /*|*/Kc::Undf, /*|*/Kc::Trns, /*|*/Kc::Trns,/*|*/Kc::Trns, /*|          |*/Kc::Trns,/*|*/Kc::Trns, /*|*/Kc::Trns,/*|*/Kc::Undf,/*|*/Kc::Undf,/*|*/Kc::Undf/*|*/],
                 /*               +-------------+---------------+--------------+--------------+-------------+--------------+          +-------------+--------------+-------------+-------------+-------------+------------+*/
             ],
         ],
 
-        combos: [Kc::ComboCtrlD]
+        combos: &[
+            Combo {
+                keys: &[ComboKey::Key(Kc::ModCo), ComboKey::Key(Kc::D)],
+                action: &[Kc::ModCo, Kc::Bksp],
+                layers: &[],
+            },
+            // `(` and `)` together type both parens
+            Combo {
+                keys: &[ComboKey::Pos(0, 7), ComboKey::Pos(0, 8)],
+                action: &[Action::Macro(PARENS)],
+                layers: &[1],
+            },
+        ],
+
+        conditional_layers: &[],
+
+        tap_dances: &[],
     } 
 }
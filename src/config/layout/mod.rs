@@ -12,13 +12,69 @@ pub mod colemakdh;
 
 use crate::{
     config::{enums::*, user_config::*},
-    matrix::PinMatrix,
+    layer::{ConditionalLayer, LayerState},
+    matrix::KeyPos,
 };
 
+#[cfg(target_os = "espidf")]
+use crate::matrix::esp::EspPinMatrix;
+
+#[cfg(all(feature = "direct-pins", target_os = "espidf"))]
+use crate::matrix::esp::EspDirectPins;
+
+/// Tap dance action, selected by the number of taps of a `Kc::TD` key
+///
+/// The tap dance table is a `&'static` slice, a call in it like `S(Kc::Scn)` or `Kc::MO(1)`
+/// is not promoted to a static inside `layout()` (E0716), so the table is a const item:
+///
+/// ```
+/// use esp32_rustboard::config::{enums::*, layout::TapDance};
+///
+/// // a single tap sends `;`, a double tap sends `:` and holding the key activates layer 1
+/// const TAP_DANCES: &[TapDance] = &[TapDance {
+///     taps: &[Kc::Scn, S(Kc::Scn)],
+///     hold: Kc::MO(1),
+/// }];
+/// # assert_eq!(TAP_DANCES[0].taps[1], S(Kc::Scn));
+/// ```
+///
+/// set as `tap_dances: TAP_DANCES` in the layout, the key is `Kc::TD(0)`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TapDance {
+    /// the action sent for one, two, ... taps
+    pub taps: &'static [Action],
+    /// the action used while the key is held, `Kc::No` to hold the tapped action instead
+    pub hold: Action,
+}
+
+/// Key of a combo, matched by its physical position or by its action
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComboKey {
+    /// row and col of the key, as in the keymap
+    Pos(u8, u8),
+    /// action of the key on the active layer
+    Key(Action),
+}
+
+/// Combo, pressing all of its keys within the combo term sends the combo action
+///
+/// Example: `Combo { keys: &[ComboKey::Pos(1, 2), ComboKey::Pos(1, 3)], action: &[Kc::ModCo, Kc::Bksp], layers: &[] }`
+/// pressing both keys together sends `Ctrl + Backspace` on every layer
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Combo {
+    pub keys: &'static [ComboKey],
+    /// the actions pressed together while the combo is held (key, modifier, layer, macro or mouse)
+    pub action: &'static [Action],
+    /// the layers the combo is enabled on, all layers if empty
+    pub layers: &'static [usize],
+}
+
 #[derive(Default)]
 pub struct Layout {
-    pub keymap: [[[Kc; COLS * 2]; ROWS]; LAYERS],
-    pub combos: [Kc; USER_SET_COMBO_NUMBER],
+    pub keymap: [[[Action; COLS * 2]; ROWS]; LAYERS],
+    pub combos: &'static [Combo],
+    pub conditional_layers: &'static [ConditionalLayer],
+    pub tap_dances: &'static [TapDance],
 }
 
 impl Layout {
@@ -37,20 +93,28 @@ impl Layout {
         return colemakdh::layout();
     }
 
-    /// get the layer number
-    pub fn get_layer(layer: &Kc) -> usize {
-        match layer {
-            Kc::L1 => 1,
-            Kc::L2 => 2,
-            Kc::L3 => 3,
-            Kc::L4 => 4,
-            Kc::L5 => 5,
-            _ => 0,
+    /// get the action at the key position
+    /// transparent keys fall through to the next lower active layer
+    pub fn get_action(&self, position: &KeyPos, layer_state: &LayerState) -> Action {
+        for layer in (0..=position.layer).rev() {
+            if layer != position.layer && !layer_state.is_active(layer) {
+                continue;
+            }
+
+            let action = self.keymap[layer][position.row as usize][position.col as usize];
+
+            if action != Action::Trns {
+                return action;
+            }
         }
+
+        // transparent down to the base layer
+        Action::No
     }
 }
 
-pub fn provide_kb_matrix() -> PinMatrix<'static> {
+#[cfg(target_os = "espidf")]
+pub fn provide_kb_matrix() -> EspPinMatrix {
     let pin_matrix;
 
     // Dvorak Layouts Start
@@ -77,8 +141,122 @@ pub fn provide_kb_matrix() -> PinMatrix<'static> {
     #[cfg(feature = "colemakdh")]
     {
         use crate::config::layout::colemakdh;
-        pin_matrix = provide_pin_matrix();
+        pin_matrix = colemakdh::provide_pin_matrix();
     }
 
     pin_matrix
 }
+
+/// Switches wired straight to the gpios, for the boards without a key matrix
+///
+/// Each switch is placed at its row and col of the keymap,
+/// with the pull and the level of its gpio while pressed
+#[cfg(all(feature = "direct-pins", target_os = "espidf"))]
+pub fn provide_kb_direct_pins() -> EspDirectPins {
+    use crate::matrix::esp::DirectGpio;
+    use embedded_hal::digital::PinState;
+    use esp_idf_hal::{
+        gpio::{IOPin, Pull},
+        prelude::Peripherals,
+    };
+
+    let peripherals = Peripherals::take().expect("Not able to init peripherals.");
+
+    // switches to ground, on the home row
+    EspDirectPins::from_gpios([
+        DirectGpio::new(
+            peripherals.pins.gpio4.downgrade(),
+            1,
+            0,
+            Pull::Up,
+            PinState::Low,
+        ),
+        DirectGpio::new(
+            peripherals.pins.gpio5.downgrade(),
+            1,
+            1,
+            Pull::Up,
+            PinState::Low,
+        ),
+        DirectGpio::new(
+            peripherals.pins.gpio7.downgrade(),
+            1,
+            2,
+            Pull::Up,
+            PinState::Low,
+        ),
+        DirectGpio::new(
+            peripherals.pins.gpio6.downgrade(),
+            1,
+            3,
+            Pull::Up,
+            PinState::Low,
+        ),
+        DirectGpio::new(
+            peripherals.pins.gpio10.downgrade(),
+            1,
+            4,
+            Pull::Up,
+            PinState::Low,
+        ),
+        DirectGpio::new(
+            peripherals.pins.gpio3.downgrade(),
+            1,
+            5,
+            Pull::Up,
+            PinState::Low,
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(layer: usize) -> KeyPos {
+        KeyPos::new(1, 2, layer)
+    }
+
+    fn layout(base: Action, upper: Action) -> Layout {
+        let mut layout = Layout::default();
+        layout.keymap[0][1][2] = base;
+        layout.keymap[1][1][2] = upper;
+        layout
+    }
+
+    #[test]
+    fn transparent_key_falls_through_to_the_active_lower_layer() {
+        let mut layer_state = LayerState::new(0);
+        layer_state.on(1);
+
+        assert_eq!(
+            layout(Kc::A, Kc::Trns).get_action(&position(1), &layer_state),
+            Kc::A
+        );
+        assert_eq!(
+            layout(Kc::A, Kc::B).get_action(&position(1), &layer_state),
+            Kc::B
+        );
+    }
+
+    #[test]
+    fn transparent_key_skips_the_inactive_lower_layers() {
+        // layer 0 is not active, layer 1 is the default layer
+        let layer_state = LayerState::new(1);
+
+        assert_eq!(
+            layout(Kc::A, Kc::Trns).get_action(&position(1), &layer_state),
+            Kc::No
+        );
+    }
+
+    #[test]
+    fn transparent_key_down_to_the_base_layer_does_nothing() {
+        let layer_state = LayerState::new(0);
+
+        assert_eq!(
+            layout(Kc::Trns, Kc::Trns).get_action(&position(1), &layer_state),
+            Kc::No
+        );
+    }
+}
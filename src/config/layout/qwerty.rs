@@ -1,48 +1,32 @@
-use crate::{
-    config::{enums::*, layout::*},
-    matrix::{KeyPos, PinMatrix},
-};
-use esp_idf_hal::{
-    gpio::{IOPin, PinDriver},
-    prelude::Peripherals,
+use crate::config::{enums::*, layout::*};
+
+#[cfg(target_os = "espidf")]
+use {
+    crate::matrix::esp::EspPinMatrix,
+    esp_idf_hal::{gpio::IOPin, prelude::Peripherals},
 };
 
-pub fn provide_pin_matrix() -> PinMatrix<'static> {
+#[cfg(target_os = "espidf")]
+pub fn provide_pin_matrix() -> EspPinMatrix {
     let peripherals = Peripherals::take().expect("Not able to init peripherals.");
 
     let rows = [
-        PinDriver::output(peripherals.pins.gpio12.downgrade())
-            .expect("Not able to set port as output."),
-        PinDriver::output(peripherals.pins.gpio18.downgrade())
-            .expect("Not able to set port as output."),
-        PinDriver::output(peripherals.pins.gpio19.downgrade())
-            .expect("Not able to set port as output."),
-        PinDriver::output(peripherals.pins.gpio20.downgrade())
-            .expect("Not able to set port as output."),
+        peripherals.pins.gpio12.downgrade(),
+        peripherals.pins.gpio18.downgrade(),
+        peripherals.pins.gpio19.downgrade(),
+        peripherals.pins.gpio20.downgrade(),
     ];
 
     let cols = [
-        PinDriver::input(peripherals.pins.gpio4.downgrade())
-            .expect("Not able to set port as input."),
-        PinDriver::input(peripherals.pins.gpio5.downgrade())
-            .expect("Not able to set port as input."),
-        PinDriver::input(peripherals.pins.gpio7.downgrade())
-            .expect("Not able to set port as input."),
-        PinDriver::input(peripherals.pins.gpio6.downgrade())
-            .expect("Not able to set port as input."),
-        PinDriver::input(peripherals.pins.gpio10.downgrade())
-            .expect("Not able to set port as input."),
-        PinDriver::input(peripherals.pins.gpio3.downgrade())
-            .expect("Not able to set port as input."),
+        peripherals.pins.gpio4.downgrade(),
+        peripherals.pins.gpio5.downgrade(),
+        peripherals.pins.gpio7.downgrade(),
+        peripherals.pins.gpio6.downgrade(),
+        peripherals.pins.gpio10.downgrade(),
+        peripherals.pins.gpio3.downgrade(),
     ];
 
-    let registered_local_keys_array = [KeyPos::default(); 6];
-
-    PinMatrix {
-        rows,
-        cols,
-        registered_local_keys_array,
-    }
+    EspPinMatrix::from_gpios(rows, cols)
 }
 
 //*********************************************************************************************
@@ -83,17 +67,21 @@ pub fn layout() -> Layout {
             [
                 /*  LAYER 1 */  /*     COL 0          COL 1        COL 2          COL 3         COL 4         COL 5                   COL 6         COL 7         COL 8        COL 9         COL 10        COL 11   */
                 /*                 +--------------+----------------------------+--------------+-------------+--------------+        +-------------+--------------+-------------+-------------+--------------+------------+*/
-                /*   ROW 0  */  [/*|*/Kc::Esc,  /*|*/Kc::Undf, /*|*/Kc::N7,  /*|*/Kc::N8,   /*|*/Kc::N9,  /*|*/Kc::Pscr, /*|        |*/Kc::Undf,/*|*/Kc::MaLP, /*|*/Kc::MaRP,/*|*/Kc::Undf,/*|*/Kc::Undf, /*|*/Kc::Undf/*|*/],
+                /*   ROW 0  */  [/*|*/Kc::Esc,  /*|*/Kc::Undf, /*|*/Kc::N7,  /*|*/Kc::N8,   /*|*/Kc::N9,  /*|*/Kc::Pscr, /*|        |*/Kc::Undf,/*|*/S(Kc::N9),/*|*/S(Kc::N0),/*|*/Kc::Undf,/*|*/Kc::Undf, /*|*/Kc::Undf/*|*/],
                 /*                 +--------------+----------------------------+--------------+-------------+--------------+        +-------------+--------------+-------------+-------------+--------------+------------+*/
                 /*   ROW 1  */  [/*|*/Kc::Bksp, /*|*/Kc::ModAl,/*|*/Kc::N4,  /*|*/Kc::N5,   /*|*/Kc::N6,  /*|*/Kc::Del,  /*|        |*/Kc::Undf,/*|*/Kc::ArL,  /*|*/Kc::ArD, /*|*/Kc::ArU, /*|*/Kc::ArR,  /*|*/Kc::Undf/*|*/],
                 /*                 +--------------+--------------+-------------+--------------+-------------+--------------+        +-------------+--------------+-------------+-------------+--------------+------------+*/
-                /*   ROW 2  */  [/*|*/Kc::ModCo,/*|*/Kc::N0,   /*|*/Kc::N1,  /*|*/Kc::N2,   /*|*/Kc::N3,  /*|*/Kc::MaSL, /*|        |*/Kc::Bksl,/*|*/Kc::Lbrk, /*|*/Kc::Rbrk,/*|*/Kc::Grav, /*|*/Kc::Undf, /*|*/Kc::Undf/*|*/],
+                /*   ROW 2  */  [/*|*/Kc::ModCo,/*|*/Kc::N0,   /*|*/Kc::N1,  /*|*/Kc::N2,   /*|*/Kc::N3,  /*|*/G(Kc::L), /*|        |*/Kc::Bksl,/*|*/Kc::Lbrk, /*|*/Kc::Rbrk,/*|*/Kc::Grav, /*|*/Kc::Undf, /*|*/Kc::Undf/*|*/],
                 /*                 +--------------+--------------+-------------+--------------+-------------+--------------+        +-------------+--------------+-------------+-------------+--------------+------------+*/
-                /*   ROW 3  */  [/*|*/Kc::Undf, /*|*/Kc::Undf, /*|*/Kc::Undf,/*|*/Kc::ModSu,/*|*/Kc::Spac,/*|*/Kc::ModSh,/*|        |*/Kc::Tab, /*|*/Kc::Entr ,/*|*/Kc::L1,  /*|*/Kc::Undf,/*|*/Kc::Undf, /*|*/Kc::Undf/*|*/],
+                /*   ROW 3  */  [/*|*/Kc::Undf, /*|*/Kc::Undf, /*|*/Kc::Undf,/*|*/Kc::Trns, /*|*/Kc::Trns,/*|*/Kc::Trns, /*|        |*/Kc::Trns,/*|*/Kc::Trns ,/*|*/Kc::Trns,/*|*/Kc::Undf,/*|*/Kc::Undf, /*|*/Kc::Undf/*|*/],
                 /*                 +--------------+--------------+-------------+--------------+-------------+--------------+        +-------------+--------------+-------------+-------------+--------------+------------+*/
             ],
         ],
 
-        combos: [Kc::Undf]
+        combos: &[],
+
+        conditional_layers: &[],
+
+        tap_dances: &[],
     } 
 }
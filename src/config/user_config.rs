@@ -1,6 +1,10 @@
 use embassy_time::Duration;
+#[cfg(target_os = "espidf")]
 use esp32_nimble::{utilities::BleUuid, uuid128};
 
+use crate::matrix::DiodeDirection;
+use crate::mouse::AccelerationCurve;
+
 //USER CONFIGURABLE PARAMETERS
 
 pub static KB_NAME: &str = "Rustboard_5x3";
@@ -10,21 +14,48 @@ pub const MASTER_BLE_MAC: &str = "E4:B0:63:22:EB:EA"; //Rustboard_Rosewood
 //Rows/Cols per half
 pub const ROWS: usize = 4;
 pub const COLS: usize = 6;
+pub const DIODE_DIRECTION: DiodeDirection = DiodeDirection::Row2Col; // Row2Col drives the rows and reads the cols, Col2Row the other way
 
-pub const LAYERS: usize = 2;
-
-// Set the number of combo keys
-pub const USER_SET_COMBO_NUMBER: usize = 1;
+pub const LAYERS: usize = 2; // keymaps in every layout, a tri-layer needs 4
+pub const DEFAULT_LAYER: usize = 0;
 
-//Cursor parameters
-pub const CURSOR_PARAM_FAST: u8 = 6;
-pub const CURSOR_PARAM_NORMAL: u8 = 2;
-pub const CURSOR_PARAM_SLOW: u8 = 0;
+// Mouse key related params
+pub const MOUSEKEY_DELAY: Duration = Duration::from_millis(10); // before the cursor keeps moving after the first move
+pub const MOUSEKEY_INTERVAL: Duration = Duration::from_millis(16); // between the cursor moves
+pub const MOUSEKEY_MOVE_DELTA: u8 = 2; // cursor step of the first move
+pub const MOUSEKEY_MAX_SPEED: u8 = 16; // cursor step at full speed, max 127
+pub const MOUSEKEY_TIME_TO_MAX: Duration = Duration::from_millis(800); // from the move delta to the max speed
+pub const MOUSEKEY_CURVE: AccelerationCurve = AccelerationCurve::Linear; // Constant, Linear or Quadratic
+pub const MOUSEKEY_SLOW_SPEED: u8 = 1; // cursor step while Kc::MoCS is held, Kc::MoCF uses the max speed
+pub const MOUSEKEY_WHEEL_DELAY: Duration = Duration::from_millis(100); // before the wheel keeps scrolling after the first notch
+pub const MOUSEKEY_WHEEL_INTERVAL: Duration = Duration::from_millis(16); // between the scroll reports
+pub const MOUSEKEY_WHEEL_SPEED: u8 = 8; // notches per second when the wheel starts scrolling
+pub const MOUSEKEY_WHEEL_MAX_SPEED: u8 = 40; // notches per second at full speed
+pub const MOUSEKEY_WHEEL_TIME_TO_MAX: Duration = Duration::from_millis(1000); // from the wheel speed to the max speed
+pub const MOUSEKEY_WHEEL_RESOLUTION: u8 = 16; // high resolution wheel units per notch, if enabled by the host, max 127
+pub const MOUSE_WARP_TIMEOUT: Duration = Duration::from_millis(2000); // a later warp key starts from the whole screen again
 
 // Debounce related params
 pub const BLE_STATUS_DEBOUNCE: Duration = Duration::from_millis(500); //0.5 sec
 pub const ENTER_SLEEP_DEBOUNCE: Duration = Duration::from_millis(600000); //10 minutes
 
+// Tap-hold related params
+pub const TAPPING_TERM: Duration = Duration::from_millis(200); // also the time to tap a tap dance key again
+pub const PERMISSIVE_HOLD: bool = true; // hold if another key is pressed and released within the tapping term
+
+// Combo related params
+pub const COMBO_TERM: Duration = Duration::from_millis(50); // all the combo keys have to be pressed within
+
+// One-shot related params
+pub const ONESHOT_TIMEOUT: Duration = Duration::from_millis(5000); // unused one-shot keys are cleared after
+pub const ONESHOT_TAP_TOGGLE: bool = true; // tap a one-shot key twice to lock it
+
+// Macro related params
+pub const MACRO_QUEUE_SIZE: usize = 4; // macros pressed while the queue is full are dropped
+
+// Keyboard report related params
+pub const NKRO_ENABLED: bool = false; // start with the n-key rollover report, switched at runtime with Kc::NkTg
+
 #[cfg(feature = "async-scan")]
 pub const ASYNC_ROW_WAIT: u64 = 2;
 
@@ -33,20 +64,26 @@ pub const REGISTERED_KEYS_ARRAY_SIZE: usize = 12;
 pub const LAYER_INDEXMAP_SIZE: usize = 64;
 
 pub const BIT_SHIFT: u8 = 4;
+#[cfg(target_os = "espidf")]
 pub const BLE_SLAVE_UUID: BleUuid = uuid128!("06984d74-0fdb-491e-9c4c-c25603a9bc34");
+#[cfg(target_os = "espidf")]
+pub const BLE_HOST_LED_UUID: BleUuid = uuid128!("3b1f7a52-8c0e-4d6a-9e27-5a4c81d0f6b3");
 
 #[cfg(feature = "master")]
 pub mod master {
+    #[cfg(target_os = "espidf")]
     use crate::EspPowerLevel;
     use embassy_time::Duration;
 
     pub const COL_OFFSET: u8 = 0;
     pub const KEY_DEBOUNCE: Duration = Duration::from_millis(20);
+    #[cfg(target_os = "espidf")]
     pub const ESP_POWER_LEVEL: EspPowerLevel = EspPowerLevel::Negative0;
 }
 
 #[cfg(feature = "slave")]
 pub mod slave {
+    #[cfg(target_os = "espidf")]
     use crate::EspPowerLevel;
     use embassy_time::Duration;
 
@@ -54,5 +91,6 @@ pub mod slave {
 
     pub const COL_OFFSET: u8 = COLS as u8;
     pub const KEY_DEBOUNCE: Duration = Duration::from_millis(10);
+    #[cfg(target_os = "espidf")]
     pub const ESP_POWER_LEVEL: EspPowerLevel = EspPowerLevel::Negative0;
 }
@@ -1,6 +1,7 @@
 use crate::{
     delay::delay_ms,
     matrix::{KeyState, RegisteredMatrixKeys},
+    mutex::Mutex,
 };
 use embassy_time::Instant;
 
@@ -12,7 +13,6 @@ use crate::config::user_config::slave::KEY_DEBOUNCE;
 
 extern crate alloc;
 use alloc::sync::Arc;
-use esp32_nimble::utilities::mutex::Mutex;
 
 pub async fn calculate_debounce(registered_matrix_keys: &Arc<Mutex<RegisteredMatrixKeys>>) -> ! {
     loop {
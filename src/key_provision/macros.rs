@@ -0,0 +1,288 @@
+use crate::ble::KeyboardKeyReport;
+use crate::config::enums::{Action, HidKey, HidModifiers, MacroStep};
+use crate::config::user_config::MACRO_QUEUE_SIZE;
+use embassy_time::{Duration, Instant};
+use heapless::Deque;
+
+/// Macro sequence player
+///
+/// The pressed macros are queued and played step by step,
+/// at most one change of the keyboard report per call,
+/// so every step is sent in its own report and the scanning is never blocked
+#[derive(Debug)]
+pub struct MacroPlayer {
+    queue: Deque<&'static [MacroStep], MACRO_QUEUE_SIZE>,
+    step: usize,
+    character: usize,
+    released: bool,
+    next_step_time: Instant,
+}
+
+impl Default for MacroPlayer {
+    fn default() -> Self {
+        Self {
+            queue: Deque::new(),
+            step: 0,
+            character: 0,
+            released: true,
+            next_step_time: Instant::now(),
+        }
+    }
+}
+
+impl MacroPlayer {
+    /// Register the first report of a pressed key, queue it if it is a macro sequence
+    pub fn key_pressed(&mut self, action: &Action) {
+        match action {
+            Action::Macro(steps) => self
+                .queue
+                .push_back(steps)
+                .unwrap_or_else(|_| log::warn!("Macro queue full, the macro is dropped")),
+            Action::Combo(combo_action) => combo_action
+                .iter()
+                .for_each(|valid_action| self.key_pressed(valid_action)),
+            _ => {}
+        }
+    }
+
+    /// Play the next step of the current macro
+    pub fn play(&mut self, keyboard_key_report: &mut KeyboardKeyReport) {
+        while let Some(steps) = self.queue.front().copied() {
+            if Instant::now() < self.next_step_time {
+                return;
+            }
+
+            let Some(step) = steps.get(self.step) else {
+                // the macro has finished, continue with the next one
+                self.queue.pop_front();
+                self.step = 0;
+                continue;
+            };
+
+            match *step {
+                MacroStep::Press(key) => {
+                    press(keyboard_key_report, &key, false);
+                    self.step += 1;
+                    return;
+                }
+                MacroStep::Release(key) => {
+                    release(keyboard_key_report, &key, false);
+                    self.step += 1;
+                    return;
+                }
+                MacroStep::Tap(key) => {
+                    self.released = !self.released;
+                    if self.released {
+                        release(keyboard_key_report, &key, false);
+                        self.step += 1;
+                    } else {
+                        press(keyboard_key_report, &key, false);
+                    }
+                    return;
+                }
+                MacroStep::Delay(milliseconds) => {
+                    self.next_step_time = Instant::now() + Duration::from_millis(milliseconds);
+                    self.step += 1;
+                }
+                MacroStep::Text(text) => {
+                    let Some(character) = text.as_bytes().get(self.character) else {
+                        self.character = 0;
+                        self.step += 1;
+                        continue;
+                    };
+
+                    // skip the characters which can not be typed
+                    let Some((key, shift)) = HidKey::from_ascii(*character) else {
+                        self.character += 1;
+                        continue;
+                    };
+
+                    self.released = !self.released;
+                    if self.released {
+                        release(keyboard_key_report, &Action::Key(key), shift);
+                        self.character += 1;
+                    } else {
+                        press(keyboard_key_report, &Action::Key(key), shift);
+                    }
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Add the key to the keyboard report
+fn press(keyboard_key_report: &mut KeyboardKeyReport, action: &Action, shift: bool) {
+    if shift {
+        keyboard_key_report.modifiers |= HidModifiers::Shift as u8;
+    }
+
+    let (hid_code, modifiers) = report_parts(action);
+    keyboard_key_report.modifiers |= modifiers;
+
+    if hid_code != 0 && !keyboard_key_report.keys.contains(&hid_code) {
+        if let Some(index) = keyboard_key_report
+            .keys
+            .iter()
+            .position(|&value| value == 0)
+        {
+            keyboard_key_report.keys[index] = hid_code;
+        }
+    }
+}
+
+/// Remove the key from the keyboard report
+fn release(keyboard_key_report: &mut KeyboardKeyReport, action: &Action, shift: bool) {
+    if shift {
+        keyboard_key_report.modifiers &= !(HidModifiers::Shift as u8);
+    }
+
+    let (hid_code, modifiers) = report_parts(action);
+    keyboard_key_report.modifiers &= !modifiers;
+
+    if let Some(index) = keyboard_key_report
+        .keys
+        .iter()
+        .position(|&value| hid_code != 0 && value == hid_code)
+    {
+        keyboard_key_report.keys[index] = 0;
+    }
+}
+
+/// Get the HID code and the modifiers of the action, only keys and modifiers can be played
+fn report_parts(action: &Action) -> (u8, u8) {
+    match *action {
+        Action::Key(hid_key) => (hid_key as u8, 0),
+        Action::Modifier(modifiers) => (0, modifiers),
+        Action::WithModifiers(hid_key, modifiers) => (hid_key as u8, modifiers),
+        _ => (0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::enums::{Kc, S};
+    use crate::matrix::sim::mock_time;
+    use embassy_time::MockDriver;
+
+    const SHIFT: u8 = HidModifiers::Shift as u8;
+    const EXCLAMATION: Action = S(Kc::N1);
+
+    /// Play the macro until it has finished, returns every change of the report
+    fn play(steps: &'static [MacroStep]) -> std::vec::Vec<(u8, u8)> {
+        let mut macro_player = MacroPlayer::default();
+        let mut keyboard_key_report = KeyboardKeyReport::default();
+        let mut reports = std::vec::Vec::new();
+
+        macro_player.key_pressed(&Action::Macro(steps));
+
+        while !macro_player.queue.is_empty() {
+            let previous_report = keyboard_key_report;
+            macro_player.play(&mut keyboard_key_report);
+
+            if keyboard_key_report != previous_report {
+                reports.push((keyboard_key_report.modifiers, keyboard_key_report.keys[0]));
+            }
+            MockDriver::get().advance(Duration::from_millis(1));
+        }
+
+        reports
+    }
+
+    #[test]
+    fn steps_are_played_in_order_one_report_change_at_a_time() {
+        let _time = mock_time();
+
+        assert_eq!(
+            play(&[
+                MacroStep::Press(Kc::LSft),
+                MacroStep::Tap(Kc::A),
+                MacroStep::Release(Kc::LSft),
+                MacroStep::Tap(EXCLAMATION),
+            ]),
+            [
+                (SHIFT, 0),
+                (SHIFT, HidKey::A as u8),
+                (SHIFT, 0),
+                (0, 0),
+                (SHIFT, HidKey::N1 as u8),
+                (0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_play_changes_the_report_once() {
+        let _time = mock_time();
+        let mut macro_player = MacroPlayer::default();
+        let mut keyboard_key_report = KeyboardKeyReport::default();
+
+        macro_player.key_pressed(&Action::Macro(&[
+            MacroStep::Tap(Kc::A),
+            MacroStep::Tap(Kc::B),
+        ]));
+
+        macro_player.play(&mut keyboard_key_report);
+        assert_eq!(keyboard_key_report.keys[0], HidKey::A as u8);
+
+        macro_player.play(&mut keyboard_key_report);
+        assert_eq!(keyboard_key_report.keys[0], 0);
+
+        macro_player.play(&mut keyboard_key_report);
+        assert_eq!(keyboard_key_report.keys[0], HidKey::B as u8);
+    }
+
+    #[test]
+    fn delay_holds_the_next_step_back() {
+        let _time = mock_time();
+        let mut macro_player = MacroPlayer::default();
+        let mut keyboard_key_report = KeyboardKeyReport::default();
+
+        macro_player.key_pressed(&Action::Macro(&[
+            MacroStep::Delay(10),
+            MacroStep::Tap(Kc::A),
+        ]));
+
+        // the delay starts once its step is played
+        macro_player.play(&mut keyboard_key_report);
+        assert_eq!(keyboard_key_report.keys[0], 0);
+
+        MockDriver::get().advance(Duration::from_millis(9));
+        macro_player.play(&mut keyboard_key_report);
+        assert_eq!(keyboard_key_report.keys[0], 0);
+
+        MockDriver::get().advance(Duration::from_millis(1));
+        macro_player.play(&mut keyboard_key_report);
+        assert_eq!(keyboard_key_report.keys[0], HidKey::A as u8);
+    }
+
+    #[test]
+    fn text_is_typed_with_shift_for_the_shifted_characters() {
+        let _time = mock_time();
+
+        assert_eq!(
+            play(&[MacroStep::Text("aB!\u{7f}")]),
+            [
+                (0, HidKey::A as u8),
+                (0, 0),
+                (SHIFT, HidKey::B as u8),
+                (0, 0),
+                (SHIFT, HidKey::N1 as u8),
+                (0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn macro_pressed_while_the_queue_is_full_is_dropped() {
+        let _time = mock_time();
+        let mut macro_player = MacroPlayer::default();
+
+        for _ in 0..=MACRO_QUEUE_SIZE {
+            macro_player.key_pressed(&Action::Macro(&[MacroStep::Tap(Kc::A)]));
+        }
+
+        assert_eq!(macro_player.queue.len(), MACRO_QUEUE_SIZE);
+    }
+}
@@ -1,119 +1,373 @@
 extern crate alloc;
 use alloc::sync::Arc;
-use esp32_nimble::utilities::mutex::Mutex;
 use heapless::Vec;
 
 use crate::{
     ble::KeyboardKeyReport,
-    matrix::{KeyState, RegisteredMatrixKeys},
+    matrix::{KeyPos, KeyState, RegisteredMatrixKeys},
+    mutex::Mutex,
 };
 
+#[cfg(feature = "master")]
+pub mod macros;
+#[cfg(feature = "master")]
+pub mod one_shot;
+#[cfg(feature = "master")]
+pub mod tap_dance;
+#[cfg(feature = "master")]
+pub mod tap_hold;
+
 #[cfg(feature = "slave")]
-use crate::{config::user_config::BIT_SHIFT, matrix::KeyPos};
+use crate::config::user_config::BIT_SHIFT;
 
 #[cfg(feature = "master")]
 use crate::{
+    ble::{MediaKeyReport, NkroKeyReport, SystemControlReport},
     config::{
-        enums::{HidModifiers, Kc, KeyType},
+        enums::{Action, HidKey, LayerAction},
         layout::Layout,
     },
+    layer::LayerState,
+    matrix::{Key, TapHoldState},
     mouse::MouseKeyReport,
 };
 
 #[cfg(feature = "master")]
+use {macros::MacroPlayer, one_shot::OneShot};
+
+#[cfg(feature = "master")]
+/// Add the action of a pressed key to the reports, on every pass while the key is held
+///
+/// The toggle layer and the n-key rollover keys act once, on release in `remove_keys_master`
+#[allow(clippy::too_many_arguments)]
 fn add_keys_master(
     keyboard_key_report: &mut KeyboardKeyReport,
+    nkro_key_report: &mut NkroKeyReport,
+    media_key_report: &mut MediaKeyReport,
+    system_control_report: &mut SystemControlReport,
     mouse_key_report: &mut MouseKeyReport,
-    hid_key: &Kc,
-    layer: &Arc<Mutex<usize>>,
+    action: &Action,
+    tap_hold: TapHoldState,
+    layer: &Arc<Mutex<LayerState>>,
 ) {
-    // get the key type
-    match KeyType::check_type(hid_key) {
-        KeyType::Combo => {
-            let (combo_valid_keys, _keys_to_remove) = Kc::get_combo(hid_key);
-            for valid_key in combo_valid_keys.iter() {
-                add_keys_master(keyboard_key_report, mouse_key_report, valid_key, layer);
-            }
-        }
-        KeyType::Macro => {
-            let macro_valid_keys = Kc::get_macro_sequence(hid_key);
-            for valid_key in macro_valid_keys.iter() {
-                add_keys_master(keyboard_key_report, mouse_key_report, valid_key, layer);
+    // get the action type
+    match *action {
+        Action::Combo(combo_action) => {
+            for valid_action in combo_action.iter() {
+                add_keys_master(
+                    keyboard_key_report,
+                    nkro_key_report,
+                    media_key_report,
+                    system_control_report,
+                    mouse_key_report,
+                    valid_action,
+                    tap_hold,
+                    layer,
+                );
             }
         }
-        KeyType::Layer => {
-            // check and set the layer
-            *layer.lock() = Layout::get_layer(hid_key);
+        // the macro sequences are played by the macro player
+        Action::Macro(_) => {}
+        Action::WithModifiers(hid_key, modifiers) => {
+            // the modifiers are held only while the key is held
+            keyboard_key_report.modifiers |= modifiers;
+            add_hid_key(keyboard_key_report, nkro_key_report, hid_key);
         }
-        KeyType::Modifier => {
-            keyboard_key_report.modifiers |= HidModifiers::get_modifier(hid_key);
+        Action::Layer(layer_action) => match layer_action {
+            LayerAction::Toggle(_) => {}
+            LayerAction::To(layer_number) => layer.lock().to(layer_number),
+            LayerAction::Default(layer_number) => layer.lock().set_default(layer_number),
+            LayerAction::Momentary(layer_number) => layer.lock().on(layer_number),
+        },
+        Action::Modifier(modifiers) => {
+            keyboard_key_report.modifiers |= modifiers;
         }
-        KeyType::Mouse => {
+        Action::Mouse(mouse_action) => {
             // set the mouse command to the mouse ble characteristic
-            mouse_key_report.set_command(hid_key);
+            mouse_key_report.set_command(&mouse_action);
         }
-        KeyType::Key => {
-            // check if the key count is less than 6
-            if !keyboard_key_report.keys.contains(&(*hid_key as u8)) {
-                // find the first key slot in the array that is free
-                if let Some(index) = keyboard_key_report
-                    .keys
-                    .iter()
-                    .position(|&value| value == 0)
-                {
-                    // add the new key to that position
-                    keyboard_key_report.keys[index] = *hid_key as u8
-                }
-            }
+        Action::Consumer(consumer_key) => {
+            // the last pressed media key is sent
+            media_key_report.usage = consumer_key as u16;
+        }
+        Action::SystemControl(system_control_key) => {
+            system_control_report.usage = system_control_key as u8;
         }
+        Action::Nkro(_) => {}
+        Action::ModTap(tap_action, modifier) => match tap_hold {
+            TapHoldState::Tap => add_keys_master(
+                keyboard_key_report,
+                nkro_key_report,
+                media_key_report,
+                system_control_report,
+                mouse_key_report,
+                tap_action,
+                tap_hold,
+                layer,
+            ),
+            TapHoldState::Hold => keyboard_key_report.modifiers |= modifier as u8,
+            // wait for the tap-hold decision
+            TapHoldState::Undecided => {}
+        },
+        Action::LayerTap(layer_number, tap_action) => match tap_hold {
+            TapHoldState::Tap => add_keys_master(
+                keyboard_key_report,
+                nkro_key_report,
+                media_key_report,
+                system_control_report,
+                mouse_key_report,
+                tap_action,
+                tap_hold,
+                layer,
+            ),
+            TapHoldState::Hold => layer.lock().on(layer_number),
+            TapHoldState::Undecided => {}
+        },
+        // an unresolved tap dance key is held back, the resolved action is processed instead
+        Action::TapDance(_) => {}
+        // while held, a one-shot key acts like a regular modifier or momentary layer
+        Action::OneShotModifier(modifier) => keyboard_key_report.modifiers |= modifier as u8,
+        Action::OneShotLayer(layer_number) => layer.lock().on(layer_number),
+        // transparent keys left after the layer fall through and no-op keys do nothing
+        Action::Trns | Action::No => {}
+        Action::Key(hid_key) => add_hid_key(keyboard_key_report, nkro_key_report, hid_key),
     }
 }
 
 #[cfg(feature = "master")]
+/// Remove the action of a released key from the reports
+#[allow(clippy::too_many_arguments)]
 fn remove_keys_master(
     keyboard_key_report: &mut KeyboardKeyReport,
+    nkro_key_report: &mut NkroKeyReport,
+    media_key_report: &mut MediaKeyReport,
+    system_control_report: &mut SystemControlReport,
     mouse_key_report: &mut MouseKeyReport,
-    hid_key: &Kc,
-    layer: &Arc<Mutex<usize>>,
+    action: &Action,
+    tap_hold: TapHoldState,
+    layer: &Arc<Mutex<LayerState>>,
+    one_shot: &mut OneShot,
 ) {
-    // get the key type
-    match KeyType::check_type(hid_key) {
-        KeyType::Combo => {
-            let (combo_valid_keys, _keys_to_change) = Kc::get_combo(hid_key);
-            for valid_key in combo_valid_keys.iter() {
-                remove_keys_master(keyboard_key_report, mouse_key_report, valid_key, layer);
-            }
-        }
-
-        KeyType::Macro => {
-            let macro_valid_keys = Kc::get_macro_sequence(hid_key);
-            for valid_key in macro_valid_keys.iter() {
-                remove_keys_master(keyboard_key_report, mouse_key_report, valid_key, layer);
+    // get the action type
+    match *action {
+        Action::Combo(combo_action) => {
+            for valid_action in combo_action.iter() {
+                remove_keys_master(
+                    keyboard_key_report,
+                    nkro_key_report,
+                    media_key_report,
+                    system_control_report,
+                    mouse_key_report,
+                    valid_action,
+                    tap_hold,
+                    layer,
+                    one_shot,
+                );
             }
         }
-        KeyType::Layer => {
-            // set previous layer
-            *layer.lock() -= 1;
+        Action::Macro(_) => {}
+        Action::WithModifiers(hid_key, modifiers) => {
+            keyboard_key_report.modifiers &= !modifiers;
+            remove_hid_key(keyboard_key_report, nkro_key_report, hid_key);
         }
-        KeyType::Modifier => {
+        Action::Layer(layer_action) => match layer_action {
+            LayerAction::Toggle(layer_number) => layer.lock().toggle(layer_number),
+            LayerAction::To(_) | LayerAction::Default(_) => {}
+            // release the momentary layer
+            LayerAction::Momentary(layer_number) => layer.lock().off(layer_number),
+        },
+        Action::Modifier(modifiers) => {
             // remove the modifier
-            keyboard_key_report.modifiers &= !HidModifiers::get_modifier(hid_key);
+            keyboard_key_report.modifiers &= !modifiers;
         }
-        KeyType::Mouse => {
+        Action::Mouse(mouse_action) => {
             // remove the mouse command from the mouse ble characteristic
-            mouse_key_report.reset_keypress(hid_key);
+            mouse_key_report.reset_keypress(&mouse_action);
         }
-        KeyType::Key => {
-            // find the key index of the released key
-            if let Some(index) = keyboard_key_report
-                .keys
-                .iter()
-                .position(|&value| value == *hid_key as u8)
-            {
-                // remove the key from the key slot
-                keyboard_key_report.keys[index] = 0
+        Action::Consumer(consumer_key) => {
+            // release the media key only if no other media key has been pressed after it
+            if media_key_report.usage == consumer_key as u16 {
+                media_key_report.usage = 0;
             }
         }
+        Action::SystemControl(system_control_key) => {
+            if system_control_report.usage == system_control_key as u8 {
+                system_control_report.usage = 0;
+            }
+        }
+        Action::Nkro(nkro_action) => nkro_key_report.set_command(&nkro_action),
+        Action::ModTap(tap_action, modifier) => match tap_hold {
+            TapHoldState::Tap => remove_keys_master(
+                keyboard_key_report,
+                nkro_key_report,
+                media_key_report,
+                system_control_report,
+                mouse_key_report,
+                tap_action,
+                tap_hold,
+                layer,
+                one_shot,
+            ),
+            TapHoldState::Hold => keyboard_key_report.modifiers &= !(modifier as u8),
+            TapHoldState::Undecided => {}
+        },
+        Action::LayerTap(layer_number, tap_action) => match tap_hold {
+            TapHoldState::Tap => remove_keys_master(
+                keyboard_key_report,
+                nkro_key_report,
+                media_key_report,
+                system_control_report,
+                mouse_key_report,
+                tap_action,
+                tap_hold,
+                layer,
+                one_shot,
+            ),
+            TapHoldState::Hold => layer.lock().off(layer_number),
+            TapHoldState::Undecided => {}
+        },
+        Action::TapDance(_) => {}
+        Action::OneShotModifier(modifier) => {
+            keyboard_key_report.modifiers &= !(modifier as u8);
+            // arm the one-shot key if it has been tapped
+            one_shot.key_released(action, keyboard_key_report, layer);
+        }
+        Action::OneShotLayer(layer_number) => {
+            layer.lock().off(layer_number);
+            one_shot.key_released(action, keyboard_key_report, layer);
+        }
+        Action::Trns | Action::No => {}
+        Action::Key(hid_key) => remove_hid_key(keyboard_key_report, nkro_key_report, hid_key),
+    }
+}
+
+#[cfg(feature = "master")]
+/// Add the HID usage of the key to the keyboard report
+fn add_hid_key(
+    keyboard_key_report: &mut KeyboardKeyReport,
+    nkro_key_report: &mut NkroKeyReport,
+    hid_key: HidKey,
+) {
+    let hid_code = hid_key as u8;
+
+    // the n-key rollover report holds all the pressed keys
+    if hid_code != 0 {
+        nkro_key_report.add_key(hid_code);
+    }
+
+    // check if the key count is less than 6
+    if hid_code != 0 && !keyboard_key_report.keys.contains(&hid_code) {
+        // find the first key slot in the array that is free
+        if let Some(index) = keyboard_key_report
+            .keys
+            .iter()
+            .position(|&value| value == 0)
+        {
+            // add the new key to that position
+            keyboard_key_report.keys[index] = hid_code
+        }
+    }
+}
+
+#[cfg(feature = "master")]
+/// Remove the HID usage of the key from the keyboard report
+fn remove_hid_key(
+    keyboard_key_report: &mut KeyboardKeyReport,
+    nkro_key_report: &mut NkroKeyReport,
+    hid_key: HidKey,
+) {
+    let hid_code = hid_key as u8;
+
+    nkro_key_report.remove_key(hid_code);
+
+    // find the key index of the released key
+    if let Some(index) = keyboard_key_report
+        .keys
+        .iter()
+        .position(|&value| hid_code != 0 && value == hid_code)
+    {
+        // remove the key from the key slot
+        keyboard_key_report.keys[index] = 0
+    }
+}
+
+#[cfg(feature = "master")]
+/// Get the modifiers the action holds while its key is held
+fn held_modifiers(action: &Action, tap_hold: TapHoldState) -> u8 {
+    match *action {
+        Action::Combo(combo_action) => combo_action.iter().fold(0, |modifiers, action| {
+            modifiers | held_modifiers(action, tap_hold)
+        }),
+        Action::WithModifiers(_, modifiers) | Action::Modifier(modifiers) => modifiers,
+        Action::ModTap(_, modifier) if tap_hold == TapHoldState::Hold => modifier as u8,
+        Action::ModTap(tap_action, _) | Action::LayerTap(_, tap_action)
+            if tap_hold == TapHoldState::Tap =>
+        {
+            held_modifiers(tap_action, tap_hold)
+        }
+        Action::OneShotModifier(modifier) => modifier as u8,
+        _ => 0,
+    }
+}
+
+#[cfg(feature = "master")]
+/// Add the modifiers of the held keys back to the keyboard report
+///
+/// A released key clears its modifier bits, even the ones another held key shares
+fn restore_held_modifiers(keyboard_key_report: &mut KeyboardKeyReport, keys: &[Key]) {
+    for key in keys
+        .iter()
+        .filter(|key| key.info.state == KeyState::Pressed && key.info.reported)
+    {
+        keyboard_key_report.modifiers |= held_modifiers(&key.action, key.info.tap_hold);
+    }
+}
+
+#[cfg(feature = "master")]
+/// Get the layers the action holds while its key is held, one bit per layer
+fn held_layers(action: &Action, tap_hold: TapHoldState) -> u32 {
+    match *action {
+        Action::Combo(combo_action) => combo_action
+            .iter()
+            .fold(0, |layers, action| layers | held_layers(action, tap_hold)),
+        Action::Layer(LayerAction::Momentary(layer_number))
+        | Action::OneShotLayer(layer_number) => layer_bit(layer_number),
+        Action::LayerTap(layer_number, _) if tap_hold == TapHoldState::Hold => {
+            layer_bit(layer_number)
+        }
+        Action::ModTap(tap_action, _) | Action::LayerTap(_, tap_action)
+            if tap_hold == TapHoldState::Tap =>
+        {
+            held_layers(tap_action, tap_hold)
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(feature = "master")]
+/// Bit of the layer in the held layers, none for a layer out of the bitmask
+fn layer_bit(layer_number: usize) -> u32 {
+    1u32.checked_shl(layer_number as u32).unwrap_or(0)
+}
+
+#[cfg(feature = "master")]
+/// Turn the layers of the held keys back on
+///
+/// A released key turns its layer off, even if another held key holds the same layer
+fn restore_held_layers(layer: &Arc<Mutex<LayerState>>, keys: &[Key]) {
+    let held_layers = keys
+        .iter()
+        .filter(|key| key.info.state == KeyState::Pressed && key.info.reported)
+        .fold(0, |layers, key| {
+            layers | held_layers(&key.action, key.info.tap_hold)
+        });
+
+    if held_layers != 0 {
+        let mut layer = layer.lock();
+
+        (0..u32::BITS as usize)
+            .filter(|layer_number| held_layers & (1 << layer_number) != 0)
+            .for_each(|layer_number| layer.on(layer_number));
     }
 }
 
@@ -157,17 +411,22 @@ fn remove_keys_slave(keyboard_key_report: &mut KeyboardKeyReport, key_pos: &KeyP
 /// Crosschecks the key position with the layout
 /// Pnrovides the pressed key from the layout
 #[warn(unused_variables)]
+#[allow(clippy::too_many_arguments)]
 pub async fn key_provision(
     registered_matrix_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
     #[cfg(feature = "split")]
     #[cfg(feature = "master")]
     slave_key_report: &Arc<Mutex<[u8; 6]>>,
     #[cfg(feature = "master")] layout: &Layout,
-    #[cfg(feature = "master")] layer: &Arc<Mutex<usize>>,
+    #[cfg(feature = "master")] layer: &Arc<Mutex<LayerState>>,
     keyboard_key_report: &mut KeyboardKeyReport,
+    #[cfg(feature = "master")] nkro_key_report: &mut NkroKeyReport,
+    #[cfg(feature = "master")] media_key_report: &mut MediaKeyReport,
+    #[cfg(feature = "master")] system_control_report: &mut SystemControlReport,
     #[cfg(feature = "master")] mouse_key_report: &mut MouseKeyReport,
-    #[cfg(feature = "master")] registered_keys_to_remove: &mut Vec<Kc, 12>,
-    #[cfg(feature = "slave")] registered_keys_to_remove: &mut Vec<KeyPos, 12>,
+    registered_keys_to_remove: &mut Vec<KeyPos, 12>,
+    #[cfg(feature = "master")] one_shot: &mut OneShot,
+    #[cfg(feature = "master")] macro_player: &mut MacroPlayer,
 ) {
     // try to lock the hashmap
     if let Some(mut registered_matrix_keys) = registered_matrix_keys.try_lock() {
@@ -179,26 +438,52 @@ pub async fn key_provision(
         if !registered_matrix_keys.keys.is_empty() {
             #[cfg(feature = "master")]
             // transform matrix key to hid key
-            registered_matrix_keys.transform_matrix_to_hid(layout);
+            registered_matrix_keys.transform_matrix_to_hid(layout, &layer.lock());
 
             #[cfg(all(feature = "master", feature = "combo"))]
-            // process combos
-            registered_matrix_keys.process_combos(layout);
+            // process combos, keys which may still become a combo are held back
+            let combo_keys = registered_matrix_keys.process_combos(layout, &layer.lock());
+
+            #[cfg(all(feature = "master", not(feature = "combo")))]
+            let combo_keys = registered_matrix_keys.keys.len();
+
+            #[cfg(feature = "master")]
+            // resolve the tap dance and the tap-hold keys, keys pressed after an undecided one are held back,
+            // only the keys before the lowest count are processed, so the keys are sent in the pressed order
+            let processed_keys = combo_keys
+                .min(tap_dance::process_tap_dance(
+                    &mut registered_matrix_keys.keys,
+                    layout,
+                ))
+                .min(tap_hold::process_tap_hold(&mut registered_matrix_keys.keys));
+
+            #[cfg(feature = "slave")]
+            let processed_keys = registered_matrix_keys.keys.len();
 
             // iter trough the pressed keys
-            for key in registered_matrix_keys.keys.iter_mut() {
+            for key in registered_matrix_keys.keys.iter_mut().take(processed_keys) {
                 // check the key debounce state
                 match key.info.state {
                     KeyState::Pressed => {
                         #[cfg(feature = "master")]
                         {
+                            if !key.info.reported {
+                                one_shot.key_pressed(&key.action, key.info.tap_hold);
+                                macro_player.key_pressed(&key.action);
+                            }
                             // // get the pressed key from the layout
                             add_keys_master(
                                 keyboard_key_report,
+                                nkro_key_report,
+                                media_key_report,
+                                system_control_report,
                                 mouse_key_report,
-                                &key.keycode,
+                                &key.action,
+                                key.info.tap_hold,
                                 layer,
                             );
+                            // nothing is reported for a tap-hold key until it is decided
+                            key.info.reported = !tap_hold::is_undecided(key);
                         }
                         #[cfg(feature = "slave")]
                         add_keys_slave(keyboard_key_report, &key.position);
@@ -207,18 +492,41 @@ pub async fn key_provision(
                     KeyState::Released => {
                         #[cfg(feature = "master")]
                         {
-                            // get the mapped key from the layout
-                            remove_keys_master(
-                                keyboard_key_report,
-                                &mut *mouse_key_report,
-                                &key.keycode,
-                                layer,
-                            );
+                            if !key.info.reported {
+                                // the key was released before being reported (tap or held back key),
+                                // report it as pressed first, it is removed on the next pass
+                                one_shot.key_pressed(&key.action, key.info.tap_hold);
+                                macro_player.key_pressed(&key.action);
+                                add_keys_master(
+                                    keyboard_key_report,
+                                    nkro_key_report,
+                                    media_key_report,
+                                    system_control_report,
+                                    mouse_key_report,
+                                    &key.action,
+                                    key.info.tap_hold,
+                                    layer,
+                                );
+                                key.info.reported = true;
+                            } else {
+                                // get the mapped key from the layout
+                                remove_keys_master(
+                                    keyboard_key_report,
+                                    nkro_key_report,
+                                    media_key_report,
+                                    system_control_report,
+                                    &mut *mouse_key_report,
+                                    &key.action,
+                                    key.info.tap_hold,
+                                    layer,
+                                    one_shot,
+                                );
 
-                            // if key has been debounced, add it to be removed
-                            registered_keys_to_remove
-                                .push(key.keycode)
-                                .expect("Error adding a key to be removed!");
+                                // if key has been debounced, add it to be removed
+                                registered_keys_to_remove
+                                    .push(key.position)
+                                    .expect("Error adding a key to be removed!");
+                            }
                         }
                         #[cfg(feature = "slave")]
                         {
@@ -233,28 +541,193 @@ pub async fn key_provision(
                 }
             }
 
-            #[cfg(feature = "master")]
             // remove the sent keys and empty the vec
             while let Some(key) = registered_keys_to_remove.pop() {
                 if let Some(index) = registered_matrix_keys
                     .keys
                     .iter()
-                    .position(|element| element.keycode == key)
+                    .position(|element| element.position.is_same_key(&key))
                 {
                     let _removed_key = registered_matrix_keys.keys.remove(index);
                 }
             }
-            #[cfg(feature = "slave")]
-            // remove the sent keys and empty the vec
-            while let Some(key) = registered_keys_to_remove.pop() {
-                if let Some(index) = registered_matrix_keys
-                    .keys
-                    .iter()
-                    .position(|element| element.position == key)
-                {
-                    let _removed_key = registered_matrix_keys.keys.remove(index);
-                }
+
+            #[cfg(feature = "master")]
+            // the released keys have cleared their modifiers and layers, add back the ones still held
+            {
+                restore_held_modifiers(keyboard_key_report, &registered_matrix_keys.keys);
+                restore_held_layers(layer, &registered_matrix_keys.keys);
             }
         }
     }
+
+    #[cfg(feature = "master")]
+    // add the one-shot modifiers to the report
+    one_shot.apply(keyboard_key_report, layer);
+}
+
+#[cfg(all(test, feature = "master"))]
+mod tests {
+    use super::*;
+    use crate::config::enums::{HidModifiers, Kc, S};
+    use crate::matrix::{KeyInfo, KeyPos};
+
+    const SHIFT: u8 = HidModifiers::Shift as u8;
+
+    fn key(action: Action, col: u8, state: KeyState, tap_hold: TapHoldState) -> Key {
+        Key {
+            action,
+            position: KeyPos::new(0, col, 0),
+            info: KeyInfo {
+                state,
+                tap_hold,
+                reported: true,
+                ..KeyInfo::default()
+            },
+        }
+    }
+
+    /// Report the pressed keys, then release the released ones like a key provisioning pass
+    fn release(keys: &[Key]) -> KeyboardKeyReport {
+        provision(keys).0
+    }
+
+    fn provision(keys: &[Key]) -> (KeyboardKeyReport, LayerState) {
+        let mut keyboard_key_report = KeyboardKeyReport::default();
+        let mut nkro_key_report = NkroKeyReport::default();
+        let mut media_key_report = MediaKeyReport::default();
+        let mut system_control_report = SystemControlReport::default();
+        let mut mouse_key_report = MouseKeyReport::default();
+        let mut one_shot = OneShot::default();
+        let layer = Arc::new(Mutex::new(LayerState::new(0)));
+
+        for key in keys {
+            add_keys_master(
+                &mut keyboard_key_report,
+                &mut nkro_key_report,
+                &mut media_key_report,
+                &mut system_control_report,
+                &mut mouse_key_report,
+                &key.action,
+                key.info.tap_hold,
+                &layer,
+            );
+        }
+        for key in keys
+            .iter()
+            .filter(|key| key.info.state == KeyState::Released)
+        {
+            remove_keys_master(
+                &mut keyboard_key_report,
+                &mut nkro_key_report,
+                &mut media_key_report,
+                &mut system_control_report,
+                &mut mouse_key_report,
+                &key.action,
+                key.info.tap_hold,
+                &layer,
+                &mut one_shot,
+            );
+        }
+        restore_held_modifiers(&mut keyboard_key_report, keys);
+        restore_held_layers(&layer, keys);
+
+        let layer_state = *layer.lock();
+        (keyboard_key_report, layer_state)
+    }
+
+    #[test]
+    fn released_modifier_keeps_the_modifier_of_a_held_key() {
+        let keyboard_key_report = release(&[
+            key(Kc::ModSh, 0, KeyState::Pressed, TapHoldState::Undecided),
+            key(S(Kc::A), 1, KeyState::Released, TapHoldState::Undecided),
+        ]);
+
+        assert_eq!(keyboard_key_report.modifiers, SHIFT);
+    }
+
+    #[test]
+    fn released_mod_tap_hold_keeps_the_modifier_of_a_held_key() {
+        let keyboard_key_report = release(&[
+            key(Kc::ModSh, 0, KeyState::Pressed, TapHoldState::Undecided),
+            key(
+                Kc::MT(&Kc::A, HidModifiers::Shift),
+                1,
+                KeyState::Released,
+                TapHoldState::Hold,
+            ),
+        ]);
+
+        assert_eq!(keyboard_key_report.modifiers, SHIFT);
+    }
+
+    #[test]
+    fn released_one_shot_modifier_keeps_the_modifier_of_a_held_key() {
+        let keyboard_key_report = release(&[
+            key(
+                Kc::MT(&Kc::A, HidModifiers::Shift),
+                0,
+                KeyState::Pressed,
+                TapHoldState::Hold,
+            ),
+            key(
+                Kc::OSM(HidModifiers::Shift),
+                1,
+                KeyState::Released,
+                TapHoldState::Undecided,
+            ),
+        ]);
+
+        assert_eq!(keyboard_key_report.modifiers, SHIFT);
+    }
+
+    #[test]
+    fn released_modifier_is_removed_when_no_other_key_holds_it() {
+        let keyboard_key_report = release(&[
+            key(Kc::ModCo, 0, KeyState::Pressed, TapHoldState::Undecided),
+            key(S(Kc::A), 1, KeyState::Released, TapHoldState::Undecided),
+        ]);
+
+        assert_eq!(keyboard_key_report.modifiers, HidModifiers::Control as u8);
+    }
+
+    #[test]
+    fn right_hand_modifiers_set_the_right_modifier_bits() {
+        let keyboard_key_report = release(&[
+            key(Kc::RCtl, 0, KeyState::Pressed, TapHoldState::Undecided),
+            key(Kc::RSft, 1, KeyState::Pressed, TapHoldState::Undecided),
+            key(Kc::RAlt, 2, KeyState::Pressed, TapHoldState::Undecided),
+            key(Kc::RGui, 3, KeyState::Pressed, TapHoldState::Undecided),
+            key(Kc::LSft, 4, KeyState::Released, TapHoldState::Undecided),
+        ]);
+
+        assert_eq!(keyboard_key_report.modifiers, 0xF0);
+    }
+
+    #[test]
+    fn released_layer_key_keeps_the_layer_held_by_another_key() {
+        use KeyState::{Pressed, Released};
+        use TapHoldState::{Hold, Tap, Undecided};
+
+        const LAYER_TAP: Action = Kc::LT(1, &Kc::A);
+
+        for (held, tap_hold) in [
+            (Kc::MO(1), Undecided),
+            (LAYER_TAP, Hold),
+            (Kc::OSL(1), Undecided),
+        ] {
+            let keys = [
+                key(Kc::MO(1), 0, Released, Undecided),
+                key(held, 1, Pressed, tap_hold),
+            ];
+            assert!(provision(&keys).1.is_active(1));
+        }
+
+        // a tapped layer-tap key does not hold its layer
+        let keys = [
+            key(Kc::MO(1), 0, Released, Undecided),
+            key(LAYER_TAP, 1, Pressed, Tap),
+        ];
+        assert!(!provision(&keys).1.is_active(1));
+    }
 }
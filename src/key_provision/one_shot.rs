@@ -0,0 +1,281 @@
+extern crate alloc;
+use alloc::sync::Arc;
+
+use crate::ble::KeyboardKeyReport;
+use crate::config::enums::Action;
+use crate::config::user_config::{ONESHOT_TAP_TOGGLE, ONESHOT_TIMEOUT};
+use crate::layer::LayerState;
+use crate::matrix::TapHoldState;
+use crate::mutex::Mutex;
+use embassy_time::Instant;
+
+/// One-shot (sticky) modifiers and layers
+///
+/// A tapped one-shot key applies to the next pressed key only,
+/// and is cleared once the keyboard report with that key has been sent.
+/// Tapped again before being used, it stays locked until it is tapped once more.
+/// Held while another key is pressed, it acts like a regular modifier or momentary layer.
+#[derive(Debug)]
+pub struct OneShot {
+    modifiers: u8,
+    locked_modifiers: u8,
+    layer: Option<usize>,
+    locked_layer: Option<usize>,
+    armed_time: Instant,
+    triggered: bool,
+    interrupted: bool,
+}
+
+impl Default for OneShot {
+    fn default() -> Self {
+        Self {
+            modifiers: 0,
+            locked_modifiers: 0,
+            layer: None,
+            locked_layer: None,
+            armed_time: Instant::now(),
+            triggered: false,
+            interrupted: false,
+        }
+    }
+}
+
+impl OneShot {
+    /// Register the first report of a pressed key
+    pub fn key_pressed(&mut self, action: &Action, tap_hold: TapHoldState) {
+        match action {
+            // a new one-shot key press, it is a tap until another key is pressed
+            Action::OneShotModifier(_) | Action::OneShotLayer(_) => self.interrupted = false,
+            Action::Key(_)
+            | Action::WithModifiers(..)
+            | Action::Macro(_)
+            | Action::Combo(_)
+            | Action::Mouse(_) => self.trigger(),
+            Action::ModTap(..) | Action::LayerTap(..) if tap_hold == TapHoldState::Tap => {
+                self.trigger()
+            }
+            _ => {}
+        }
+    }
+
+    /// A one-shot key is released, arm it if it has been tapped
+    pub fn key_released(
+        &mut self,
+        action: &Action,
+        keyboard_key_report: &mut KeyboardKeyReport,
+        layer: &Arc<Mutex<LayerState>>,
+    ) {
+        // the key has been used as a regular modifier or momentary layer
+        if self.interrupted {
+            return;
+        }
+
+        match action {
+            Action::OneShotModifier(modifier) => {
+                let modifier = *modifier as u8;
+
+                if self.locked_modifiers & modifier == modifier {
+                    // tapped while locked, release the lock
+                    self.locked_modifiers &= !modifier;
+                    self.modifiers &= !modifier;
+                    keyboard_key_report.modifiers &= !modifier;
+                } else if ONESHOT_TAP_TOGGLE && self.modifiers & modifier == modifier {
+                    // tapped again before being used, lock it
+                    self.locked_modifiers |= modifier;
+                } else {
+                    self.arm();
+                    self.modifiers |= modifier;
+                }
+            }
+            Action::OneShotLayer(layer_number) => {
+                if self.locked_layer == Some(*layer_number) {
+                    self.locked_layer = None;
+                    self.layer = None;
+                } else if ONESHOT_TAP_TOGGLE && self.layer == Some(*layer_number) {
+                    self.locked_layer = Some(*layer_number);
+                    layer.lock().on(*layer_number);
+                } else {
+                    // only one one-shot layer at a time
+                    if let Some(previous_layer) = self.layer.take() {
+                        layer.lock().off(previous_layer);
+                    }
+                    self.arm();
+                    self.layer = Some(*layer_number);
+                    layer.lock().on(*layer_number);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply the one-shot modifiers to the keyboard report, clear them on timeout
+    pub fn apply(
+        &mut self,
+        keyboard_key_report: &mut KeyboardKeyReport,
+        layer: &Arc<Mutex<LayerState>>,
+    ) {
+        if self.is_armed() && !self.triggered && Instant::now() >= self.armed_time + ONESHOT_TIMEOUT
+        {
+            self.clear(keyboard_key_report, layer);
+        }
+
+        keyboard_key_report.modifiers |= self.modifiers | self.locked_modifiers;
+    }
+
+    /// The keyboard report has been sent, clear the one-shot state it has used
+    pub fn report_sent(
+        &mut self,
+        keyboard_key_report: &mut KeyboardKeyReport,
+        layer: &Arc<Mutex<LayerState>>,
+    ) {
+        if self.triggered {
+            self.clear(keyboard_key_report, layer);
+        }
+    }
+
+    fn is_armed(&self) -> bool {
+        self.modifiers != 0 || self.layer.is_some()
+    }
+
+    fn arm(&mut self) {
+        if !self.is_armed() {
+            self.armed_time = Instant::now();
+        }
+        self.triggered = false;
+    }
+
+    fn trigger(&mut self) {
+        self.interrupted = true;
+
+        if self.is_armed() {
+            self.triggered = true;
+        }
+    }
+
+    fn clear(
+        &mut self,
+        keyboard_key_report: &mut KeyboardKeyReport,
+        layer: &Arc<Mutex<LayerState>>,
+    ) {
+        // the held modifier keys are added back on the next key provisioning
+        keyboard_key_report.modifiers &= !(self.modifiers & !self.locked_modifiers);
+        self.modifiers = 0;
+
+        if let Some(layer_number) = self.layer.take() {
+            if self.locked_layer != Some(layer_number) {
+                layer.lock().off(layer_number);
+            }
+        }
+
+        self.triggered = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::enums::{HidKey, HidModifiers};
+    use crate::matrix::sim::mock_time;
+    use embassy_time::{Duration, MockDriver};
+
+    const SHIFT: u8 = HidModifiers::Shift as u8;
+    const ONE_SHOT_SHIFT: Action = Action::OneShotModifier(HidModifiers::Shift);
+    const KEY: Action = Action::Key(HidKey::A);
+
+    fn tap(one_shot: &mut OneShot, action: &Action, layer: &Arc<Mutex<LayerState>>) {
+        one_shot.key_pressed(action, TapHoldState::Undecided);
+        one_shot.key_released(action, &mut KeyboardKeyReport::default(), layer);
+    }
+
+    /// Apply the one-shot state to a new report, like a key provisioning pass
+    fn modifiers(one_shot: &mut OneShot, layer: &Arc<Mutex<LayerState>>) -> u8 {
+        let mut keyboard_key_report = KeyboardKeyReport::default();
+        one_shot.apply(&mut keyboard_key_report, layer);
+        keyboard_key_report.modifiers
+    }
+
+    #[test]
+    fn tapped_modifier_is_cleared_once_the_next_key_is_sent() {
+        let _time = mock_time();
+        let layer = Arc::new(Mutex::new(LayerState::new(0)));
+        let mut one_shot = OneShot::default();
+
+        tap(&mut one_shot, &ONE_SHOT_SHIFT, &layer);
+        assert_eq!(modifiers(&mut one_shot, &layer), SHIFT);
+
+        // a report without a new key keeps the modifier
+        one_shot.report_sent(&mut KeyboardKeyReport::default(), &layer);
+        assert_eq!(modifiers(&mut one_shot, &layer), SHIFT);
+
+        one_shot.key_pressed(&KEY, TapHoldState::Undecided);
+        assert_eq!(modifiers(&mut one_shot, &layer), SHIFT);
+
+        one_shot.report_sent(&mut KeyboardKeyReport::default(), &layer);
+        assert_eq!(modifiers(&mut one_shot, &layer), 0);
+    }
+
+    #[test]
+    fn unused_modifier_is_cleared_after_the_timeout() {
+        let _time = mock_time();
+        let layer = Arc::new(Mutex::new(LayerState::new(0)));
+        let mut one_shot = OneShot::default();
+
+        tap(&mut one_shot, &ONE_SHOT_SHIFT, &layer);
+
+        MockDriver::get().advance(ONESHOT_TIMEOUT - Duration::from_millis(1));
+        assert_eq!(modifiers(&mut one_shot, &layer), SHIFT);
+
+        MockDriver::get().advance(Duration::from_millis(1));
+        assert_eq!(modifiers(&mut one_shot, &layer), 0);
+    }
+
+    #[test]
+    fn tapped_twice_the_modifier_stays_locked_until_tapped_again() {
+        let _time = mock_time();
+        let layer = Arc::new(Mutex::new(LayerState::new(0)));
+        let mut one_shot = OneShot::default();
+
+        tap(&mut one_shot, &ONE_SHOT_SHIFT, &layer);
+        tap(&mut one_shot, &ONE_SHOT_SHIFT, &layer);
+
+        one_shot.key_pressed(&KEY, TapHoldState::Undecided);
+        one_shot.report_sent(&mut KeyboardKeyReport::default(), &layer);
+
+        // without the tap toggle, the second tap only arms the modifier again
+        let locked = if ONESHOT_TAP_TOGGLE { SHIFT } else { 0 };
+        assert_eq!(modifiers(&mut one_shot, &layer), locked);
+
+        if ONESHOT_TAP_TOGGLE {
+            tap(&mut one_shot, &ONE_SHOT_SHIFT, &layer);
+            assert_eq!(modifiers(&mut one_shot, &layer), 0);
+        }
+    }
+
+    #[test]
+    fn modifier_held_while_another_key_is_pressed_is_not_armed() {
+        let _time = mock_time();
+        let layer = Arc::new(Mutex::new(LayerState::new(0)));
+        let mut one_shot = OneShot::default();
+
+        one_shot.key_pressed(&ONE_SHOT_SHIFT, TapHoldState::Undecided);
+        one_shot.key_pressed(&KEY, TapHoldState::Undecided);
+        one_shot.key_released(&ONE_SHOT_SHIFT, &mut KeyboardKeyReport::default(), &layer);
+
+        assert_eq!(modifiers(&mut one_shot, &layer), 0);
+    }
+
+    #[test]
+    fn tapped_layer_is_switched_off_once_the_next_key_is_sent() {
+        let _time = mock_time();
+        let layer = Arc::new(Mutex::new(LayerState::new(0)));
+        let mut one_shot = OneShot::default();
+
+        tap(&mut one_shot, &Action::OneShotLayer(1), &layer);
+        assert!(layer.lock().is_active(1));
+
+        one_shot.key_pressed(&KEY, TapHoldState::Undecided);
+        one_shot.report_sent(&mut KeyboardKeyReport::default(), &layer);
+
+        assert!(!layer.lock().is_active(1));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::config::enums::Action;
+use crate::config::layout::Layout;
+use crate::config::user_config::TAPPING_TERM;
+use crate::matrix::{Key, KeyState};
+use embassy_time::Instant;
+
+/// Tap dance decision engine
+///
+/// A tap dance key is kept registered between its taps, every new press increases its tap count.
+/// The dance is resolved to the action from the layout tap dance table:
+/// - the tapped action, if the key is not tapped again within the tapping term after its release
+/// - the tapped action, if the last action in the table has been reached
+/// - the tapped action, if another key is pressed after it
+/// - the hold action, if the key is still pressed after the tapping term has passed
+///
+/// The resolved action replaces the tap dance action, and is processed as a regular key.
+///
+/// Returns the count of the keys to process, the keys before the first unresolved tap dance key
+pub fn process_tap_dance(keys: &mut [Key], layout: &Layout) -> usize {
+    for index in 0..keys.len() {
+        let Action::TapDance(tap_dance_index) = keys[index].action else {
+            continue;
+        };
+
+        // tap dance key without an action in the layout
+        let Some(tap_dance) = layout.tap_dances.get(tap_dance_index) else {
+            keys[index].action = Action::No;
+            continue;
+        };
+
+        let taps = (keys[index].info.taps as usize).clamp(1, tap_dance.taps.len().max(1));
+        let tapped_action = tap_dance.taps.get(taps - 1).copied().unwrap_or(Action::No);
+        let interrupted = index + 1 < keys.len();
+
+        let info = &keys[index].info;
+
+        match info.state {
+            KeyState::Pressed => {
+                if Instant::now() >= info.first_pressed_time + TAPPING_TERM {
+                    keys[index].action = if tap_dance.hold != Action::No {
+                        tap_dance.hold
+                    } else {
+                        tapped_action
+                    };
+                } else if interrupted {
+                    keys[index].action = tapped_action;
+                }
+            }
+            KeyState::Released => {
+                if interrupted
+                    || taps >= tap_dance.taps.len()
+                    || Instant::now() >= info.pressed_time + TAPPING_TERM
+                {
+                    keys[index].action = tapped_action;
+                }
+            }
+        }
+    }
+
+    // hold back the unresolved key, so it is not removed between the taps,
+    // and the keys after it
+    keys.iter().position(is_tap_dance).unwrap_or(keys.len())
+}
+
+/// Check if the key is an unresolved tap dance key
+pub fn is_tap_dance(key: &Key) -> bool {
+    matches!(key.action, Action::TapDance(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::enums::{Kc, S};
+    use crate::config::layout::TapDance;
+    use crate::matrix::{sim::mock_time, KeyInfo, KeyPos};
+    use embassy_time::{Duration, MockDriver};
+
+    const TAP_DANCES: &[TapDance] = &[TapDance {
+        taps: &[Kc::Scn, S(Kc::Scn), Kc::Entr],
+        hold: Kc::MO(1),
+    }];
+
+    fn layout() -> Layout {
+        Layout {
+            tap_dances: TAP_DANCES,
+            ..Layout::default()
+        }
+    }
+
+    /// The tap dance key, tapped the number of times and released
+    fn tapped(taps: u8) -> Key {
+        let mut info = KeyInfo::new(Instant::now());
+        info.taps = taps;
+        info.state = KeyState::Released;
+
+        Key {
+            action: Kc::TD(0),
+            position: KeyPos::new(0, 0, 0),
+            info,
+        }
+    }
+
+    fn wait(duration: Duration) {
+        MockDriver::get().advance(duration);
+    }
+
+    #[test]
+    fn tap_count_is_resolved_once_the_tapping_term_has_passed() {
+        for (taps, action) in [(1, Kc::Scn), (2, S(Kc::Scn))] {
+            let _time = mock_time();
+            let mut keys = [tapped(taps)];
+
+            wait(TAPPING_TERM - Duration::from_millis(1));
+
+            // the key may still be tapped again, it is held back
+            assert_eq!(process_tap_dance(&mut keys, &layout()), 0);
+            assert_eq!(keys[0].action, Kc::TD(0));
+
+            wait(Duration::from_millis(1));
+
+            assert_eq!(process_tap_dance(&mut keys, &layout()), 1);
+            assert_eq!(keys[0].action, action);
+        }
+    }
+
+    #[test]
+    fn last_tap_of_the_table_is_resolved_right_away() {
+        let _time = mock_time();
+        let mut keys = [tapped(3)];
+
+        process_tap_dance(&mut keys, &layout());
+
+        assert_eq!(keys[0].action, Kc::Entr);
+    }
+
+    #[test]
+    fn held_for_the_tapping_term_is_resolved_to_the_hold_action() {
+        let _time = mock_time();
+        let mut keys = [tapped(1)];
+        keys[0].info.state = KeyState::Pressed;
+
+        wait(TAPPING_TERM);
+        process_tap_dance(&mut keys, &layout());
+
+        assert_eq!(keys[0].action, Kc::MO(1));
+    }
+
+    #[test]
+    fn key_pressed_after_it_resolves_the_tap_count() {
+        let _time = mock_time();
+        let mut keys = [
+            tapped(2),
+            Key {
+                action: Kc::A,
+                position: KeyPos::new(0, 1, 0),
+                info: KeyInfo::new(Instant::now()),
+            },
+        ];
+
+        assert_eq!(process_tap_dance(&mut keys, &layout()), 2);
+        assert_eq!(keys[0].action, S(Kc::Scn));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::config::enums::Action;
+use crate::config::user_config::{PERMISSIVE_HOLD, TAPPING_TERM};
+use crate::matrix::{Key, KeyState, TapHoldState};
+use embassy_time::Instant;
+
+/// Tap-hold decision engine
+///
+/// A tap-hold key is resolved as:
+/// - tap, if it is released before the tapping term has passed
+/// - hold, if it is still pressed after the tapping term has passed
+/// - hold, if another key pressed after it is released within the tapping term (permissive hold)
+///
+/// Returns the count of the keys to process, ending with the first tap-hold key not reported yet
+pub fn process_tap_hold(keys: &mut [Key]) -> usize {
+    for index in 0..keys.len() {
+        if !is_undecided(&keys[index]) {
+            continue;
+        }
+
+        if keys[index].info.state == KeyState::Released {
+            keys[index].info.tap_hold = TapHoldState::Tap;
+        } else if Instant::now() >= keys[index].info.first_pressed_time + TAPPING_TERM
+            || (PERMISSIVE_HOLD
+                && keys[index + 1..]
+                    .iter()
+                    .any(|other| other.info.state == KeyState::Released))
+        {
+            keys[index].info.tap_hold = TapHoldState::Hold;
+        }
+    }
+
+    // hold back the keys after an undecided tap-hold key,
+    // and after a decision that has not been reported yet,
+    // so the tap is sent first and the held layer applies to the keys after it
+    keys.iter()
+        .position(|key| is_tap_hold(key) && !key.info.reported)
+        .map_or(keys.len(), |index| index + 1)
+}
+
+/// Check if the key is a tap-hold key
+pub fn is_tap_hold(key: &Key) -> bool {
+    matches!(key.action, Action::ModTap(..) | Action::LayerTap(..))
+}
+
+/// Check if the key is a tap-hold key waiting for the decision
+pub fn is_undecided(key: &Key) -> bool {
+    is_tap_hold(key) && key.info.tap_hold == TapHoldState::Undecided
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::enums::{HidModifiers, Kc};
+    use crate::matrix::{sim::mock_time, KeyInfo, KeyPos};
+    use embassy_time::{Duration, MockDriver};
+
+    const MOD_TAP: Action = Kc::MT(&Kc::A, HidModifiers::Control);
+
+    fn press(action: Action, col: u8) -> Key {
+        Key {
+            action,
+            position: KeyPos::new(0, col, 0),
+            info: KeyInfo::new(Instant::now()),
+        }
+    }
+
+    fn wait(millis: u64) {
+        MockDriver::get().advance(Duration::from_millis(millis));
+    }
+
+    #[test]
+    fn released_within_the_tapping_term_is_a_tap() {
+        let _time = mock_time();
+        let mut keys = [press(MOD_TAP, 0)];
+
+        wait(TAPPING_TERM.as_millis() - 1);
+        keys[0].info.state = KeyState::Released;
+
+        assert_eq!(process_tap_hold(&mut keys), 1);
+        assert_eq!(keys[0].info.tap_hold, TapHoldState::Tap);
+    }
+
+    #[test]
+    fn held_for_the_tapping_term_is_a_hold() {
+        let _time = mock_time();
+        let mut keys = [press(MOD_TAP, 0), press(Kc::B, 1)];
+
+        wait(TAPPING_TERM.as_millis() - 1);
+
+        // undecided, the key pressed after it is held back
+        assert_eq!(process_tap_hold(&mut keys), 1);
+        assert_eq!(keys[0].info.tap_hold, TapHoldState::Undecided);
+
+        wait(1);
+
+        assert_eq!(process_tap_hold(&mut keys), 1);
+        assert_eq!(keys[0].info.tap_hold, TapHoldState::Hold);
+
+        // reported, the keys after it are processed
+        keys[0].info.reported = true;
+        assert_eq!(process_tap_hold(&mut keys), 2);
+    }
+
+    #[test]
+    fn key_tapped_within_the_tapping_term_makes_it_a_permissive_hold() {
+        let _time = mock_time();
+        let mut keys = [press(MOD_TAP, 0), press(Kc::B, 1)];
+
+        wait(10);
+        process_tap_hold(&mut keys);
+        assert_eq!(keys[0].info.tap_hold, TapHoldState::Undecided);
+
+        keys[1].info.state = KeyState::Released;
+        process_tap_hold(&mut keys);
+
+        // without the permissive hold, the key waits for the tapping term
+        let decision = if PERMISSIVE_HOLD {
+            TapHoldState::Hold
+        } else {
+            TapHoldState::Undecided
+        };
+        assert_eq!(keys[0].info.tap_hold, decision);
+    }
+
+    #[test]
+    fn keys_pressed_before_the_tap_hold_key_are_not_held_back() {
+        let _time = mock_time();
+        let mut keys = [press(Kc::B, 1), press(MOD_TAP, 0), press(Kc::C, 2)];
+
+        assert_eq!(process_tap_hold(&mut keys), 2);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::config::user_config::LAYERS;
+
+// the active layers are stored in a u32 bitmask
+const _: () = assert!(LAYERS <= 32, "A maximum of 32 layers is supported.");
+
+/// Conditional layer, activated while all of its condition layers are active
+///
+/// The layers are only evaluated up to `LAYERS` of the user config,
+/// so a tri-layer needs `LAYERS = 4` and a keymap for every one of the layers:
+///
+/// ```
+/// use esp32_rustboard::layer::ConditionalLayer;
+///
+/// // layers 1 and 2 active together also activate layer 3
+/// const TRI_LAYER: &[ConditionalLayer] = &[ConditionalLayer {
+///     if_layers: &[1, 2],
+///     then_layer: 3,
+/// }];
+/// # assert_eq!(TRI_LAYER[0].then_layer, 3);
+/// ```
+///
+/// set as `conditional_layers: TRI_LAYER` in the layout
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ConditionalLayer {
+    pub if_layers: &'static [usize],
+    pub then_layer: usize,
+}
+
+/// Layer state, shared between the matrix scan and the key provisioning
+///
+/// The active layers are kept in a bitmask on top of the default layer,
+/// the keys are resolved against the highest active layer.
+/// The conditional layers are evaluated on every layer change, whichever task makes it
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LayerState {
+    active_layers: u32,
+    default_layer: usize,
+    conditional_layers: &'static [ConditionalLayer],
+}
+
+impl LayerState {
+    pub fn new(default_layer: usize) -> Self {
+        let mut layer_state = Self::default();
+        layer_state.set_default(default_layer);
+        layer_state
+    }
+
+    /// Activate a layer
+    pub fn on(&mut self, layer: usize) {
+        if layer < LAYERS {
+            self.active_layers |= 1 << layer;
+            self.update_conditional_layers();
+        }
+    }
+
+    /// Deactivate a layer
+    pub fn off(&mut self, layer: usize) {
+        if layer < LAYERS {
+            self.active_layers &= !(1 << layer);
+            self.update_conditional_layers();
+        }
+    }
+
+    /// Toggle a layer
+    pub fn toggle(&mut self, layer: usize) {
+        if layer < LAYERS {
+            self.active_layers ^= 1 << layer;
+            self.update_conditional_layers();
+        }
+    }
+
+    /// Activate a layer and deactivate all the other layers, except the default layer
+    pub fn to(&mut self, layer: usize) {
+        if layer < LAYERS {
+            self.active_layers = 1 << layer;
+            self.update_conditional_layers();
+        }
+    }
+
+    /// Set the default layer
+    pub fn set_default(&mut self, layer: usize) {
+        if layer < LAYERS {
+            self.default_layer = layer;
+            self.update_conditional_layers();
+        }
+    }
+
+    /// Get the default layer
+    pub fn default_layer(&self) -> usize {
+        self.default_layer
+    }
+
+    /// Check if a layer is active, the default layer is always active
+    pub fn is_active(&self, layer: usize) -> bool {
+        layer == self.default_layer || (layer < LAYERS && self.active_layers & (1 << layer) != 0)
+    }
+
+    /// Set the conditional layers of the layout
+    pub fn set_conditional_layers(&mut self, conditional_layers: &'static [ConditionalLayer]) {
+        self.conditional_layers = conditional_layers;
+        self.update_conditional_layers();
+    }
+
+    /// Activate or deactivate the conditional layers based on the active layers
+    fn update_conditional_layers(&mut self) {
+        self.active_layers = evaluate_conditional_layers(
+            self.active_layers,
+            self.default_layer,
+            self.conditional_layers,
+            LAYERS,
+        );
+    }
+
+    /// Get the highest active layer
+    pub fn highest_layer(&self) -> usize {
+        let layers = self.active_layers | (1 << self.default_layer);
+
+        (u32::BITS - 1 - layers.leading_zeros()) as usize
+    }
+}
+
+/// Switch the conditional layers on or off, depending on their condition layers
+///
+/// Returns the active layers, the conditional layers above the layer count are left out
+fn evaluate_conditional_layers(
+    mut active_layers: u32,
+    default_layer: usize,
+    conditional_layers: &[ConditionalLayer],
+    layers: usize,
+) -> u32 {
+    for conditional_layer in conditional_layers
+        .iter()
+        .filter(|conditional_layer| conditional_layer.then_layer < layers)
+    {
+        if conditional_layer.if_layers.iter().all(|layer| {
+            *layer == default_layer || (*layer < layers && active_layers & (1 << layer) != 0)
+        }) {
+            active_layers |= 1 << conditional_layer.then_layer;
+        } else {
+            active_layers &= !(1 << conditional_layer.then_layer);
+        }
+    }
+
+    active_layers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the configured layer count is too small for a tri-layer,
+    // so the evaluation is tested with its own layer count
+    const TEST_LAYERS: usize = 4;
+
+    const TRI_LAYER: &[ConditionalLayer] = &[ConditionalLayer {
+        if_layers: &[1, 2],
+        then_layer: 3,
+    }];
+
+    fn layers(layers: &[usize]) -> u32 {
+        layers
+            .iter()
+            .fold(0, |active_layers, layer| active_layers | 1 << layer)
+    }
+
+    #[test]
+    fn conditional_layer_is_on_while_all_of_its_layers_are_active() {
+        let evaluate = |active: &[usize]| {
+            evaluate_conditional_layers(layers(active), 0, TRI_LAYER, TEST_LAYERS)
+        };
+
+        assert_eq!(evaluate(&[1]), layers(&[1]));
+        assert_eq!(evaluate(&[2]), layers(&[2]));
+        assert_eq!(evaluate(&[1, 2]), layers(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn conditional_layer_is_off_once_one_of_its_layers_is_released() {
+        // e.g. the one-shot layer 1 is released after the report is sent, while layer 2 is held
+        let active_layers = layers(&[2, 3]);
+
+        assert_eq!(
+            evaluate_conditional_layers(active_layers, 0, TRI_LAYER, TEST_LAYERS),
+            layers(&[2])
+        );
+    }
+
+    #[test]
+    fn default_layer_counts_as_active() {
+        let conditional_layers = &[ConditionalLayer {
+            if_layers: &[0, 1],
+            then_layer: 2,
+        }];
+
+        assert_eq!(
+            evaluate_conditional_layers(layers(&[1]), 0, conditional_layers, TEST_LAYERS),
+            layers(&[1, 2])
+        );
+        assert_eq!(
+            evaluate_conditional_layers(layers(&[1]), 3, conditional_layers, TEST_LAYERS),
+            layers(&[1])
+        );
+    }
+
+    #[test]
+    fn conditional_layer_above_the_layer_count_is_left_out() {
+        assert_eq!(
+            evaluate_conditional_layers(layers(&[1, 2]), 0, TRI_LAYER, 3),
+            layers(&[1, 2])
+        );
+    }
+
+    #[test]
+    fn layer_changes_evaluate_the_conditional_layers() {
+        // a conditional layer on the default layer fits the configured layer count
+        let mut layer_state = LayerState::new(0);
+        layer_state.set_conditional_layers(&[ConditionalLayer {
+            if_layers: &[0],
+            then_layer: LAYERS - 1,
+        }]);
+        assert_eq!(layer_state.highest_layer(), LAYERS - 1);
+
+        // switched back on by the evaluation
+        layer_state.off(LAYERS - 1);
+        assert_eq!(layer_state.highest_layer(), LAYERS - 1);
+    }
+
+    #[test]
+    fn momentary_layer_is_active_while_on() {
+        let mut layer_state = LayerState::new(0);
+
+        layer_state.on(1);
+        assert!(layer_state.is_active(1));
+        assert_eq!(layer_state.highest_layer(), 1);
+
+        layer_state.off(1);
+        assert!(!layer_state.is_active(1));
+        assert_eq!(layer_state.highest_layer(), 0);
+    }
+
+    #[test]
+    fn toggled_layer_stays_active_until_toggled_again() {
+        let mut layer_state = LayerState::new(0);
+
+        layer_state.toggle(1);
+        assert_eq!(layer_state.highest_layer(), 1);
+
+        layer_state.toggle(1);
+        assert_eq!(layer_state.highest_layer(), 0);
+    }
+
+    #[test]
+    fn to_layer_keeps_only_the_default_layer_active() {
+        let mut layer_state = LayerState::new(0);
+
+        layer_state.on(1);
+        layer_state.to(0);
+
+        assert!(!layer_state.is_active(1));
+        assert!(layer_state.is_active(0));
+    }
+
+    #[test]
+    fn default_layer_is_always_active() {
+        let mut layer_state = LayerState::new(0);
+
+        layer_state.set_default(1);
+
+        assert_eq!(layer_state.default_layer(), 1);
+        assert!(layer_state.is_active(1));
+        assert!(!layer_state.is_active(0));
+
+        // switching the other layers off leaves the default layer active
+        layer_state.off(1);
+        assert_eq!(layer_state.highest_layer(), 1);
+    }
+
+    #[test]
+    fn layer_above_the_layer_count_is_ignored() {
+        let mut layer_state = LayerState::new(0);
+
+        layer_state.on(LAYERS);
+        layer_state.toggle(LAYERS);
+        layer_state.to(LAYERS);
+        layer_state.set_default(LAYERS);
+
+        assert_eq!(layer_state, LayerState::new(0));
+    }
+}
@@ -2,6 +2,7 @@ pub mod ble;
 pub mod config;
 pub mod debounce;
 pub mod key_provision;
+pub mod layer;
 pub mod matrix;
 pub mod mouse;
 
@@ -19,6 +20,34 @@ pub mod delay {
     }
 }
 
+/// The shared state is locked by the esp32-nimble mutex on the esp,
+/// on the host by a std mutex of the same interface, so the tests run there
+pub mod mutex {
+    #[cfg(target_os = "espidf")]
+    pub use esp32_nimble::utilities::mutex::Mutex;
+
+    #[cfg(not(target_os = "espidf"))]
+    pub struct Mutex<T>(std::sync::Mutex<T>);
+
+    #[cfg(not(target_os = "espidf"))]
+    impl<T> Mutex<T> {
+        pub const fn new(data: T) -> Self {
+            Self(std::sync::Mutex::new(data))
+        }
+
+        pub fn lock(&self) -> std::sync::MutexGuard<'_, T> {
+            self.0
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+        }
+
+        pub fn try_lock(&self) -> Option<std::sync::MutexGuard<'_, T>> {
+            self.0.try_lock().ok()
+        }
+    }
+}
+
+#[cfg(target_os = "espidf")]
 use esp_idf_sys::{
     esp_power_level_t_ESP_PWR_LVL_N0, esp_power_level_t_ESP_PWR_LVL_N12,
     esp_power_level_t_ESP_PWR_LVL_N15, esp_power_level_t_ESP_PWR_LVL_N18,
@@ -29,6 +58,7 @@ use esp_idf_sys::{
     esp_power_level_t_ESP_PWR_LVL_P21, esp_power_level_t_ESP_PWR_LVL_P3,
     esp_power_level_t_ESP_PWR_LVL_P6, esp_power_level_t_ESP_PWR_LVL_P9,
 };
+#[cfg(target_os = "espidf")]
 pub enum EspPowerLevel {
     Negative24,
     Negative21,
@@ -48,6 +78,7 @@ pub enum EspPowerLevel {
     Positive21,
 }
 
+#[cfg(target_os = "espidf")]
 impl EspPowerLevel {
     pub fn convert(self) -> u32 {
         match self {
@@ -5,9 +5,10 @@ use alloc::sync::Arc;
 
 use embassy_futures::select::select3;
 use esp32_nimble::utilities::mutex::Mutex;
-use esp32_rustboard::ble::BleStatus;
-use esp32_rustboard::config::user_config::ENTER_SLEEP_DEBOUNCE;
+use esp32_rustboard::ble::{BleStatus, HostLedState};
+use esp32_rustboard::config::user_config::{DEFAULT_LAYER, ENTER_SLEEP_DEBOUNCE};
 use esp32_rustboard::debounce::calculate_debounce;
+use esp32_rustboard::layer::LayerState;
 use esp32_rustboard::matrix::{scan_grid, RegisteredMatrixKeys};
 use esp_idf_hal::task::block_on;
 
@@ -22,19 +23,31 @@ fn main() -> anyhow::Result<()> {
         Arc::new(Mutex::new(RegisteredMatrixKeys::new(ENTER_SLEEP_DEBOUNCE)));
 
     // layer state
-    let layer: Arc<Mutex<usize>> = Arc::new(Mutex::new(0));
+    let layer: Arc<Mutex<LayerState>> = Arc::new(Mutex::new(LayerState::new(DEFAULT_LAYER)));
 
     // ble connection information shared variable
     let ble_status: Arc<Mutex<BleStatus>> = Arc::new(Mutex::new(BleStatus::Connected));
 
+    // lock keys state of the host, written to the master keyboard output report and forwarded to the slave
+    let host_led_state: Arc<Mutex<HostLedState>> = Arc::new(Mutex::new(HostLedState::default()));
+
     block_on(async {
         select3(
             scan_grid(&registered_matrix_keys, &layer, &ble_status),
             calculate_debounce(&registered_matrix_keys),
             #[cfg(feature = "master")]
-            esp32_rustboard::ble::master::ble_tx(&registered_matrix_keys, &layer, &ble_status),
+            esp32_rustboard::ble::master::ble_tx(
+                &registered_matrix_keys,
+                &layer,
+                &ble_status,
+                &host_led_state,
+            ),
             #[cfg(all(feature = "split", feature = "slave"))]
-            esp32_rustboard::ble::slave::ble_tx(&registered_matrix_keys, &ble_status),
+            esp32_rustboard::ble::slave::ble_tx(
+                &registered_matrix_keys,
+                &ble_status,
+                &host_led_state,
+            ),
         )
         .await;
     });
@@ -0,0 +1,249 @@
+use super::{KeyPos, KeyScanner, RegisteredMatrixKeys};
+use crate::config::user_config::*;
+use crate::delay::*;
+use crate::layer::LayerState;
+use crate::mutex::Mutex;
+
+#[cfg(feature = "master")]
+use crate::config::user_config::master::COL_OFFSET;
+
+#[cfg(feature = "slave")]
+use crate::config::user_config::slave::COL_OFFSET;
+
+use embedded_hal::digital::{InputPin, PinState};
+#[cfg(feature = "async-scan")]
+use embedded_hal_async::digital::Wait;
+use heapless::Vec;
+
+extern crate alloc;
+use alloc::sync::Arc;
+
+/// Max switches wired straight to the pins, one per key of the keymap half
+pub const DIRECT_PINS_MAX: usize = ROWS * COLS;
+
+/// Switch wired straight to a pin
+pub struct DirectPin<I> {
+    pub pin: I,
+    /// level of the pin while the switch is pressed
+    pub active: PinState,
+    /// row and col of the switch in the keymap
+    pub row: u8,
+    pub col: u8,
+}
+
+impl<I: InputPin> DirectPin<I> {
+    fn is_pressed(&mut self) -> bool {
+        match self.active {
+            PinState::High => self.pin.is_high(),
+            PinState::Low => self.pin.is_low(),
+        }
+        .unwrap_or(false)
+    }
+}
+
+#[cfg(feature = "async-scan")]
+impl<I: Wait> DirectPin<I> {
+    async fn wait_for_pressed(&mut self) -> Result<(), I::Error> {
+        match self.active {
+            PinState::High => self.pin.wait_for_high().await,
+            PinState::Low => self.pin.wait_for_low().await,
+        }
+    }
+}
+
+/// Switches wired straight to the pins, for the boards without a key matrix
+///
+/// The pressed switches are stored as the key positions of the keymap, like the matrix does
+pub struct DirectPins<I> {
+    pub pins: Vec<DirectPin<I>, DIRECT_PINS_MAX>,
+    pub registered_local_keys_array: [KeyPos; 6],
+}
+
+impl<I: InputPin> DirectPins<I> {
+    pub fn from_pins(pins: impl IntoIterator<Item = DirectPin<I>>) -> Self {
+        let mut direct_pins = Self {
+            pins: Vec::new(),
+            registered_local_keys_array: [KeyPos::default(); 6],
+        };
+
+        for pin in pins {
+            if direct_pins.pins.push(pin).is_err() {
+                panic!("Direct pins Vec allocation full.");
+            }
+        }
+
+        direct_pins
+    }
+
+    /// Check every pin and store the pressed switches in the buffer
+    fn read_pins(&mut self, layer: usize) {
+        for pin in self.pins.iter_mut() {
+            if pin.is_pressed() {
+                // store the key in the buffer
+                if let Some(index) = self
+                    .registered_local_keys_array
+                    .iter()
+                    .position(|&element| element == KeyPos::default())
+                {
+                    self.registered_local_keys_array[index] = KeyPos {
+                        row: pin.row,
+                        col: pin.col + COL_OFFSET,
+                        layer,
+                    };
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "async-scan")]
+    /// The pins are read once a switch is pressed, or the wait time is up
+    async fn async_scan(
+        &mut self,
+        registered_matrix_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
+        layer: &Arc<Mutex<LayerState>>,
+    ) where
+        I: Wait,
+    {
+        use crate::config::user_config::ASYNC_ROW_WAIT;
+        use core::pin::pin;
+        use embassy_futures::select::{select, select_slice, Either};
+
+        // new scope so pins are accessable as mut
+        {
+            let mut futures: Vec<_, DIRECT_PINS_MAX> = self
+                .pins
+                .iter_mut()
+                .map(|pin| pin.wait_for_pressed())
+                .collect();
+
+            match select(
+                select_slice(pin!(futures.as_mut_slice())),
+                delay_ms(ASYNC_ROW_WAIT),
+            )
+            .await
+            {
+                Either::First(_) => {
+                    // switch is pressed, check all pins
+                }
+                Either::Second(()) => {
+                    // time is up, nothing is pressed
+                    return;
+                }
+            }
+        }
+
+        let highest_layer = layer.lock().highest_layer();
+        self.read_pins(highest_layer);
+
+        // store the local pressed keys in the shared pressed keys hashmap
+        if let Some(mut registered_matrix_keys) = registered_matrix_keys.try_lock() {
+            registered_matrix_keys.store_keys_local(&mut self.registered_local_keys_array);
+        }
+    }
+
+    #[cfg(not(feature = "async-scan"))]
+    /// Every pin is read on each scan
+    async fn standard_scan(
+        &mut self,
+        registered_matrix_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
+        layer: &Arc<Mutex<LayerState>>,
+    ) {
+        let highest_layer = layer.lock().highest_layer();
+        self.read_pins(highest_layer);
+
+        // store the local pressed keys in the shared pressed keys hashmap
+        if let Some(mut registered_matrix_keys) = registered_matrix_keys.try_lock() {
+            registered_matrix_keys.store_keys_local(&mut self.registered_local_keys_array);
+        }
+
+        // there is nothing to propagate, the delay lets the other tasks run
+        delay_us(100).await;
+    }
+}
+
+#[cfg(feature = "async-scan")]
+impl<I: InputPin + Wait> KeyScanner for DirectPins<I> {
+    async fn scan(
+        &mut self,
+        registered_matrix_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
+        layer: &Arc<Mutex<LayerState>>,
+    ) {
+        self.async_scan(registered_matrix_keys, layer).await;
+    }
+}
+
+#[cfg(not(feature = "async-scan"))]
+impl<I: InputPin> KeyScanner for DirectPins<I> {
+    async fn scan(
+        &mut self,
+        registered_matrix_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
+        layer: &Arc<Mutex<LayerState>>,
+    ) {
+        self.standard_scan(registered_matrix_keys, layer).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sim::{scan, SimSwitch, SimSwitchPin};
+    use super::*;
+
+    fn direct_pin(switch: &SimSwitch, row: u8, col: u8) -> DirectPin<SimSwitchPin> {
+        DirectPin {
+            pin: switch.pin(),
+            active: switch.active(),
+            row,
+            col,
+        }
+    }
+
+    #[test]
+    fn scan_registers_the_positions_of_the_pressed_switches() {
+        let switches = [
+            SimSwitch::new(PinState::Low),
+            SimSwitch::new(PinState::High),
+            SimSwitch::new(PinState::Low),
+        ];
+        let mut direct_pins = DirectPins::from_pins([
+            direct_pin(&switches[0], 0, 0),
+            direct_pin(&switches[1], 1, 3),
+            direct_pin(&switches[2], ROWS as u8 - 1, COLS as u8 - 1),
+        ]);
+        switches[1].press();
+        switches[2].press();
+
+        let positions = scan(&mut direct_pins, LayerState::new(0));
+
+        assert_eq!(
+            positions.as_slice(),
+            &[
+                KeyPos::new(1, 3 + COL_OFFSET, 0),
+                KeyPos::new(ROWS as u8 - 1, COLS as u8 - 1 + COL_OFFSET, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_follows_the_active_level_of_each_pin() {
+        // an active low pin reads high while released, an active high pin reads low
+        let switches = [
+            SimSwitch::new(PinState::Low),
+            SimSwitch::new(PinState::High),
+        ];
+        let mut direct_pins = DirectPins::from_pins([
+            direct_pin(&switches[0], 0, 1),
+            direct_pin(&switches[1], 0, 2),
+        ]);
+
+        assert!(scan(&mut direct_pins, LayerState::new(0)).is_empty());
+
+        switches[0].press();
+        let mut layer_state = LayerState::new(0);
+        layer_state.on(1);
+
+        assert_eq!(
+            scan(&mut direct_pins, layer_state).as_slice(),
+            &[KeyPos::new(0, 1 + COL_OFFSET, 1)]
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use super::{DiodeDirection, PinMatrix};
+use crate::config::layout::provide_kb_matrix;
+use crate::config::user_config::*;
+
+use esp_idf_svc::hal::gpio::{AnyIOPin, Input, InterruptType, Output, PinDriver, Pull};
+use esp_idf_sys::{self as _, esp_bt_controller_disable, gpio_int_type_t_GPIO_INTR_HIGH_LEVEL};
+
+#[cfg(feature = "direct-pins")]
+use super::direct_pins::{DirectPin, DirectPins};
+#[cfg(feature = "direct-pins")]
+use crate::config::layout::provide_kb_direct_pins;
+#[cfg(feature = "direct-pins")]
+use embedded_hal::digital::PinState;
+#[cfg(feature = "direct-pins")]
+use esp_idf_sys::gpio_int_type_t_GPIO_INTR_LOW_LEVEL;
+
+/// The keys of the home row wake the keyboard from the light sleep
+const HOME_ROW: usize = 1;
+
+/// Enter the light sleep, once the gpio wakeup is set
+/// The keyboard is restarted when a key press wakes the processor
+fn light_sleep() {
+    unsafe {
+        // disable bt before entering sleep
+        esp_bt_controller_disable();
+
+        esp_idf_sys::esp_sleep_enable_gpio_switch(false);
+
+        esp_idf_sys::esp_sleep_enable_gpio_wakeup();
+
+        #[cfg(feature = "debug")]
+        log::info!("Entering sleep...");
+
+        // enter sleep
+        esp_idf_sys::esp_light_sleep_start();
+
+        #[cfg(feature = "debug")]
+        log::info!("Woke up...");
+
+        esp_idf_sys::esp_restart();
+        // esp_bt_controller_enable(esp_bt_mode_t_ESP_BT_MODE_BLE);
+    }
+}
+
+/// The key matrix wired to the gpios of the esp
+pub type EspPinMatrix =
+    PinMatrix<PinDriver<'static, AnyIOPin, Output>, PinDriver<'static, AnyIOPin, Input>>;
+
+impl EspPinMatrix {
+    pub fn new() -> EspPinMatrix {
+        let mut pin_matrix = provide_kb_matrix();
+
+        // set input ports to proper pull and interrupt type
+        for input in pin_matrix.inputs.iter_mut() {
+            input.set_pull(Pull::Down).ok();
+            input.set_interrupt_type(InterruptType::AnyEdge).ok();
+        }
+
+        pin_matrix
+    }
+
+    /// Construct the matrix from the row and col gpios of the board,
+    /// they are set as outputs or inputs by the diode direction
+    pub fn from_gpios(rows: [AnyIOPin; ROWS], cols: [AnyIOPin; COLS]) -> EspPinMatrix {
+        Self::from_lines(
+            rows,
+            cols,
+            |gpio| PinDriver::output(gpio).expect("Not able to set port as output."),
+            |gpio| PinDriver::input(gpio).expect("Not able to set port as input."),
+        )
+    }
+
+    /// Enables interrupt on pins for wakeup
+    fn set_input_enable_sleep_interrupts(&mut self) {
+        for input in self.inputs.iter_mut() {
+            input.enable_interrupt().ok();
+        }
+    }
+
+    /// Only used for setting gpios to listen for interrup, so the processor is woken
+    fn set_light_sleep_gpio_wakeup_enable(&mut self) {
+        // the read lines which are connected to the home row keys
+        let wakeup_inputs = match DIODE_DIRECTION {
+            DiodeDirection::Row2Col => &self.inputs[..],
+            DiodeDirection::Col2Row => &self.inputs[HOME_ROW..=HOME_ROW],
+        };
+
+        for input in wakeup_inputs.iter() {
+            unsafe {
+                /* set gpios that can wake up the chip */
+                esp_idf_sys::gpio_wakeup_enable(input.pin(), gpio_int_type_t_GPIO_INTR_HIGH_LEVEL);
+            }
+        }
+    }
+
+    /// Enter light sleep mode
+    /// This function drives the home row keys high,
+    /// and sets the configured gpio to listen for interrupt (key press) in order to wake up the processor
+    pub fn enter_light_sleep_mode(&mut self) {
+        // enable interrupts
+        self.set_input_enable_sleep_interrupts();
+
+        // set gpio wakeup enable interrup
+        self.set_light_sleep_gpio_wakeup_enable();
+
+        // set the home row, or all of the cols, to high
+        match DIODE_DIRECTION {
+            DiodeDirection::Row2Col => {
+                self.outputs[HOME_ROW].set_high().unwrap();
+            }
+            DiodeDirection::Col2Row => {
+                for output in self.outputs.iter_mut() {
+                    output.set_high().unwrap();
+                }
+            }
+        }
+
+        // enter sleep mode
+        light_sleep();
+    }
+}
+
+impl Default for EspPinMatrix {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Switch wired straight to a gpio, as wired on the board
+#[cfg(feature = "direct-pins")]
+pub struct DirectGpio {
+    pub gpio: AnyIOPin,
+    pub row: u8,
+    pub col: u8,
+    pub pull: Pull,
+    /// level of the gpio while the switch is pressed
+    pub active: PinState,
+}
+
+#[cfg(feature = "direct-pins")]
+impl DirectGpio {
+    pub fn new(gpio: AnyIOPin, row: u8, col: u8, pull: Pull, active: PinState) -> Self {
+        Self {
+            gpio,
+            row,
+            col,
+            pull,
+            active,
+        }
+    }
+}
+
+/// The switches wired straight to the gpios of the esp
+#[cfg(feature = "direct-pins")]
+pub type EspDirectPins = DirectPins<PinDriver<'static, AnyIOPin, Input>>;
+
+#[cfg(feature = "direct-pins")]
+impl EspDirectPins {
+    pub fn new() -> EspDirectPins {
+        provide_kb_direct_pins()
+    }
+
+    /// Construct the direct pins from the gpios of the board, each with its own pull
+    pub fn from_gpios(gpios: impl IntoIterator<Item = DirectGpio>) -> EspDirectPins {
+        Self::from_pins(gpios.into_iter().map(|gpio| {
+            let mut pin = PinDriver::input(gpio.gpio).expect("Not able to set port as input.");
+
+            // set input port to its pull and interrupt type
+            pin.set_pull(gpio.pull).ok();
+            pin.set_interrupt_type(InterruptType::AnyEdge).ok();
+
+            DirectPin {
+                pin,
+                active: gpio.active,
+                row: gpio.row,
+                col: gpio.col,
+            }
+        }))
+    }
+
+    /// Enter light sleep mode
+    /// Every switch wakes the processor, on the level of its pressed gpio
+    pub fn enter_light_sleep_mode(&mut self) {
+        for pin in self.pins.iter_mut() {
+            // enable interrupt
+            pin.pin.enable_interrupt().ok();
+
+            let level = match pin.active {
+                PinState::High => gpio_int_type_t_GPIO_INTR_HIGH_LEVEL,
+                PinState::Low => gpio_int_type_t_GPIO_INTR_LOW_LEVEL,
+            };
+
+            unsafe {
+                /* set gpios that can wake up the chip */
+                esp_idf_sys::gpio_wakeup_enable(pin.pin.pin(), level);
+            }
+        }
+
+        // enter sleep mode
+        light_sleep();
+    }
+}
+
+#[cfg(feature = "direct-pins")]
+impl Default for EspDirectPins {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,9 +1,20 @@
+// the scanning is driven by the scan grid on the esp, on the host by the tests only
+#![cfg_attr(not(target_os = "espidf"), allow(dead_code))]
+
+#[cfg(feature = "direct-pins")]
+pub mod direct_pins;
+#[cfg(target_os = "espidf")]
+pub mod esp;
+#[cfg(test)]
+pub(crate) mod sim;
+
 use crate::ble::Debounce;
-use crate::config::enums::{Kc, KeyType};
-use crate::config::layout::{provide_kb_matrix, Layout};
+use crate::config::enums::Action;
+use crate::config::layout::{Combo, ComboKey, Layout};
 use crate::config::user_config::*;
 use crate::delay::*;
-use core::pin::pin;
+use crate::layer::LayerState;
+use crate::mutex::Mutex;
 
 #[cfg(feature = "master")]
 use crate::config::user_config::master::COL_OFFSET;
@@ -12,13 +23,9 @@ use crate::config::user_config::master::COL_OFFSET;
 use crate::config::user_config::slave::COL_OFFSET;
 
 use embassy_time::{Duration, Instant};
-use esp_idf_svc::hal::gpio::*;
-
-use esp32_nimble::utilities::mutex::Mutex;
-use esp_idf_sys::{
-    self as _, esp_bt_controller_disable, gpio_int_type_t_GPIO_INTR_HIGH_LEVEL,
-    gpio_num_t_GPIO_NUM_10, gpio_num_t_GPIO_NUM_20, gpio_num_t_GPIO_NUM_6, gpio_num_t_GPIO_NUM_7,
-};
+use embedded_hal::digital::{InputPin, OutputPin};
+#[cfg(feature = "async-scan")]
+use embedded_hal_async::digital::Wait;
 use heapless::Vec;
 
 pub use crate::ble::BleStatus;
@@ -37,6 +44,11 @@ impl KeyPos {
     pub fn new(row: u8, col: u8, layer: usize) -> KeyPos {
         KeyPos { row, col, layer }
     }
+
+    /// Check if both positions point to the same physical key, regardless of the layer
+    pub fn is_same_key(&self, other: &KeyPos) -> bool {
+        self.row == other.row && self.col == other.col
+    }
 }
 
 impl Default for KeyPos {
@@ -49,119 +61,150 @@ impl Default for KeyPos {
     }
 }
 
-pub struct PinMatrix<'a> {
-    pub rows: [PinDriver<'a, AnyIOPin, Output>; ROWS],
-    pub cols: [PinDriver<'a, AnyIOPin, Input>; COLS],
-    pub registered_local_keys_array: [KeyPos; 6],
+/// Switches of the board, wired as a key matrix or straight to the pins
+trait KeyScanner {
+    /// Scan the switches and store the pressed keys in the registered matrix keys
+    async fn scan(
+        &mut self,
+        registered_matrix_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
+        layer: &Arc<Mutex<LayerState>>,
+    );
 }
 
-impl PinMatrix<'_> {
-    pub fn new() -> PinMatrix<'static> {
-        let mut pin_matrix = provide_kb_matrix();
+/// Direction of the diodes of the matrix
+///
+/// The current flows through the diodes from the driven lines to the read lines,
+/// so it decides which lines are driven high and which are read with a pull-down
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiodeDirection {
+    /// the rows are driven, the cols are read
+    Row2Col,
+    /// the cols are driven, the rows are read
+    Col2Row,
+}
 
-        // set input ports to proper pull and interrupt type
-        for col in pin_matrix.cols.iter_mut() {
-            col.set_pull(Pull::Down).ok();
-            col.set_interrupt_type(InterruptType::AnyEdge).ok();
+impl DiodeDirection {
+    /// Number of the driven lines
+    pub const fn outputs(self) -> usize {
+        match self {
+            DiodeDirection::Row2Col => ROWS,
+            DiodeDirection::Col2Row => COLS,
         }
-
-        pin_matrix
     }
 
-    /// Enables interrupt on pins for wakeup
-    fn set_col_enable_sleep_interrupts(&mut self) {
-        for col in self.cols.iter_mut() {
-            col.enable_interrupt().ok();
+    /// Number of the read lines
+    pub const fn inputs(self) -> usize {
+        match self {
+            DiodeDirection::Row2Col => COLS,
+            DiodeDirection::Col2Row => ROWS,
         }
     }
 
-    /// Only used for setting gpios to listen for interrup, so the processor is woken
-    fn set_light_sleep_gpio_wakeup_enable(&mut self) {
-        unsafe {
-            /* set gpios that can wake up the chip */
-            esp_idf_sys::gpio_wakeup_enable(
-                gpio_num_t_GPIO_NUM_20,
-                gpio_int_type_t_GPIO_INTR_HIGH_LEVEL,
-            );
-            esp_idf_sys::gpio_wakeup_enable(
-                gpio_num_t_GPIO_NUM_10,
-                gpio_int_type_t_GPIO_INTR_HIGH_LEVEL,
-            );
-            esp_idf_sys::gpio_wakeup_enable(
-                gpio_num_t_GPIO_NUM_7,
-                gpio_int_type_t_GPIO_INTR_HIGH_LEVEL,
-            );
-            esp_idf_sys::gpio_wakeup_enable(
-                gpio_num_t_GPIO_NUM_6,
-                gpio_int_type_t_GPIO_INTR_HIGH_LEVEL,
-            );
+    /// Row and col of the key connecting the driven line to the read line
+    pub fn key_position(self, output: usize, input: usize) -> (u8, u8) {
+        match self {
+            DiodeDirection::Row2Col => (output as u8, input as u8),
+            DiodeDirection::Col2Row => (input as u8, output as u8),
         }
     }
+}
 
-    /// Enter light sleep mode
-    /// This function sets the home row to high,
-    /// and sets the configured gpio to listen for interrupt (key press) in order to wake up the processor
-    fn enter_light_sleep_mode(&mut self) {
-        // enable interrupts
-        self.set_col_enable_sleep_interrupts();
-
-        // set gpio wakeup enable interrup
-        self.set_light_sleep_gpio_wakeup_enable();
-
-        // set the home row to high
-        self.rows[1].set_high().unwrap();
-
-        // enter sleep mode
-        unsafe {
-            // disable bt before entering sleep
-            esp_bt_controller_disable();
+/// Driven lines of the matrix, the rows or the cols depending on the diode direction
+pub const OUTPUTS: usize = DIODE_DIRECTION.outputs();
 
-            esp_idf_sys::esp_sleep_enable_gpio_switch(false);
+/// Read lines of the matrix, the rows or the cols depending on the diode direction
+pub const INPUTS: usize = DIODE_DIRECTION.inputs();
 
-            esp_idf_sys::esp_sleep_enable_gpio_wakeup();
+pub struct PinMatrix<O, I> {
+    pub outputs: [O; OUTPUTS],
+    pub inputs: [I; INPUTS],
+    pub registered_local_keys_array: [KeyPos; 6],
+}
 
-            #[cfg(feature = "debug")]
-            log::info!("Entering sleep...");
+/// Scanning of the matrix, generic over the embedded-hal pins,
+/// so it runs on the esp gpios as well as on the simulated pins of the host tests
+impl<O: OutputPin, I: InputPin> PinMatrix<O, I> {
+    pub fn from_pins(outputs: [O; OUTPUTS], inputs: [I; INPUTS]) -> Self {
+        Self {
+            outputs,
+            inputs,
+            registered_local_keys_array: [KeyPos::default(); 6],
+        }
+    }
 
-            // enter sleep
-            esp_idf_sys::esp_light_sleep_start();
+    /// Construct the matrix from the row and col lines,
+    /// the lines are turned to outputs or inputs by the diode direction
+    pub fn from_lines<L>(
+        rows: [L; ROWS],
+        cols: [L; COLS],
+        output: impl Fn(L) -> O,
+        input: impl Fn(L) -> I,
+    ) -> Self {
+        let mut rows = rows.map(Some);
+        let mut cols = cols.map(Some);
+
+        let (outputs, inputs) = match DIODE_DIRECTION {
+            DiodeDirection::Row2Col => (&mut rows[..], &mut cols[..]),
+            DiodeDirection::Col2Row => (&mut cols[..], &mut rows[..]),
+        };
+
+        Self::from_pins(
+            core::array::from_fn(|index| output(outputs[index].take().unwrap())),
+            core::array::from_fn(|index| input(inputs[index].take().unwrap())),
+        )
+    }
 
-            #[cfg(feature = "debug")]
-            log::info!("Woke up...");
+    /// Check the inputs while the output is driven and store the pressed keys in the buffer
+    fn read_inputs(&mut self, output: usize, layer: usize) {
+        for (input_count, input) in self.inputs.iter_mut().enumerate() {
+            // check if an input is set to high (key pressed)
+            if input.is_high().unwrap_or(false) {
+                let (row, col) = DIODE_DIRECTION.key_position(output, input_count);
 
-            esp_idf_sys::esp_restart();
-            // esp_bt_controller_enable(esp_bt_mode_t_ESP_BT_MODE_BLE);
+                // store the key in the buffer
+                if let Some(index) = self
+                    .registered_local_keys_array
+                    .iter()
+                    .position(|&element| element == KeyPos::default())
+                {
+                    self.registered_local_keys_array[index] = KeyPos {
+                        row,
+                        col: col + COL_OFFSET,
+                        layer,
+                    };
+                }
+            }
         }
     }
 
     #[cfg(feature = "async-scan")]
     /// This is the standard scan mode
-    /// Each row is set to high, then each col is checked if it is high or not
+    /// Each output is set to high, then each input is checked if it is high or not
     async fn async_scan(
         &mut self,
         pressed_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
-        layer: &Arc<Mutex<usize>>,
-    ) {
-        // initialize counts
-
+        layer: &Arc<Mutex<LayerState>>,
+    ) where
+        I: Wait,
+    {
         use crate::config::user_config::ASYNC_ROW_WAIT;
+        use core::pin::pin;
         use embassy_futures::select::{select, select_slice, Either};
-        use heapless::Vec;
 
-        // check rows and cols
-        for (row_count, row) in self.rows.iter_mut().enumerate() {
-            // set row to high
-            row.set_high().unwrap();
+        // check outputs and inputs
+        for output_count in 0..OUTPUTS {
+            // set output to high
+            self.outputs[output_count].set_high().ok();
 
             // delay so pin can propagate
             delay_us(1).await;
 
-            // new scope so cols are accessable as mut
+            // new scope so inputs are accessable as mut
             {
-                let mut futures: Vec<_, COLS> = self
-                    .cols
+                let mut futures: Vec<_, INPUTS> = self
+                    .inputs
                     .iter_mut()
-                    .map(|col| col.wait_for_high())
+                    .map(|input| input.wait_for_high())
                     .collect();
 
                 match select(
@@ -171,37 +214,23 @@ impl PinMatrix<'_> {
                 .await
                 {
                     Either::First(_) => {
-                        // key is pressed, check all cols
+                        // key is pressed, check all inputs
                     }
                     Either::Second(()) => {
-                        // set row to low
-                        row.set_low().unwrap();
-                        // time is up, continue with the next row
+                        // set output to low
+                        self.outputs[output_count].set_low().ok();
+                        // time is up, continue with the next output
                         continue;
                     }
                 }
             }
 
-            // check col pins
-            for (col_count, col) in self.cols.iter().enumerate() {
-                if col.is_high() {
-                    // store the key in the buffer
-                    if let Some(index) = self
-                        .registered_local_keys_array
-                        .iter()
-                        .position(|&element| element == KeyPos::default())
-                    {
-                        self.registered_local_keys_array[index] = KeyPos {
-                            row: row_count as u8,
-                            col: col_count as u8 + COL_OFFSET,
-                            layer: *layer.lock(),
-                        };
-                    }
-                }
-            }
+            // check input pins
+            let highest_layer = layer.lock().highest_layer();
+            self.read_inputs(output_count, highest_layer);
 
-            // set row to low
-            row.set_low().unwrap();
+            // set output to low
+            self.outputs[output_count].set_low().ok();
         }
 
         // store the local pressed keys in the shared pressed keys hashmap
@@ -212,57 +241,28 @@ impl PinMatrix<'_> {
 
     #[cfg(not(feature = "async-scan"))]
     /// This is the standard scan mode
-    /// Each row is set to high, then each col is checked if it is high or not
+    /// Each output is set to high, then each input is checked if it is high or not
     async fn standard_scan(
         &mut self,
         registered_matrix_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
-        layer: &Arc<Mutex<usize>>,
+        layer: &Arc<Mutex<LayerState>>,
     ) {
-        // initialize counts
-        let mut count: KeyPos = KeyPos::new(0, COL_OFFSET, 255);
-
-        // check rows and cols
-        for row in self.rows.iter_mut() {
-            // set row to high
-            row.set_high().unwrap();
+        // check outputs and inputs
+        for output_count in 0..OUTPUTS {
+            // set output to high
+            self.outputs[output_count].set_high().ok();
 
             // delay so pin can propagate
             delay_us(100).await;
 
-            // check if a col is high
-            for col in self.cols.iter() {
-                // check if a col is set to high (key pressed)
-                if col.is_high() {
-                    // store the key in the buffer
-                    if let Some(index) = self
-                        .registered_local_keys_array
-                        .iter()
-                        .position(|&element| element == KeyPos::default())
-                    {
-                        self.registered_local_keys_array[index] = KeyPos {
-                            row: count.row,
-                            col: count.row,
-                            layer: *layer.lock(),
-                        };
-                    }
-                }
-                // increment col
-                count.col += 1;
-            }
-
-            // set row to low
-            row.set_low().unwrap();
-
-            // increment row
-            count.row += 1;
+            // check if an input is high
+            let highest_layer = layer.lock().highest_layer();
+            self.read_inputs(output_count, highest_layer);
 
-            // reset col count
-            count.col = COL_OFFSET;
+            // set output to low
+            self.outputs[output_count].set_low().ok();
         }
 
-        // reset row count
-        count.row = 0;
-
         // store the local pressed keys in the shared pressed keys hashmap
         if let Some(mut registered_matrix_keys) = registered_matrix_keys.try_lock() {
             registered_matrix_keys.store_keys_local(&mut self.registered_local_keys_array);
@@ -270,34 +270,106 @@ impl PinMatrix<'_> {
     }
 }
 
+#[cfg(feature = "async-scan")]
+impl<O: OutputPin, I: InputPin + Wait> KeyScanner for PinMatrix<O, I> {
+    async fn scan(
+        &mut self,
+        registered_matrix_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
+        layer: &Arc<Mutex<LayerState>>,
+    ) {
+        self.async_scan(registered_matrix_keys, layer).await;
+    }
+}
+
+#[cfg(not(feature = "async-scan"))]
+impl<O: OutputPin, I: InputPin> KeyScanner for PinMatrix<O, I> {
+    async fn scan(
+        &mut self,
+        registered_matrix_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
+        layer: &Arc<Mutex<LayerState>>,
+    ) {
+        self.standard_scan(registered_matrix_keys, layer).await;
+    }
+}
+
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum KeyState {
     Released,
     Pressed,
 }
 
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum TapHoldState {
+    #[default]
+    Undecided,
+    Tap,
+    Hold,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct KeyInfo {
     pub pressed_time: Instant,
+    pub first_pressed_time: Instant,
     pub state: KeyState,
+    pub tap_hold: TapHoldState,
+    pub taps: u8,
+    pub reported: bool,
 }
 
 impl Default for KeyInfo {
     fn default() -> Self {
         Self {
             pressed_time: Instant::now(),
+            first_pressed_time: Instant::now(),
             state: KeyState::Released,
+            tap_hold: TapHoldState::Undecided,
+            taps: 1,
+            reported: false,
         }
     }
 }
 
+impl KeyInfo {
+    /// Construct the info of a newly pressed key
+    pub fn new(pressed_time: Instant) -> Self {
+        Self {
+            pressed_time,
+            first_pressed_time: pressed_time,
+            state: KeyState::Pressed,
+            tap_hold: TapHoldState::Undecided,
+            taps: 1,
+            reported: false,
+        }
+    }
+
+    /// Refresh the info of a key that is still being pressed
+    pub fn refresh(&mut self) {
+        self.pressed_time = Instant::now();
+        self.state = KeyState::Pressed;
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Key {
-    pub keycode: Kc,
+    pub action: Action,
     pub position: KeyPos,
     pub info: KeyInfo,
 }
 
+impl Key {
+    /// Refresh a key that is still being pressed
+    ///
+    /// A tap dance key pressed again before it has been removed counts one more tap
+    pub fn refresh(&mut self) {
+        if matches!(self.action, Action::TapDance(_)) && self.info.state == KeyState::Released {
+            self.info.taps = self.info.taps.saturating_add(1);
+            self.info.first_pressed_time = Instant::now();
+        }
+
+        self.info.refresh();
+    }
+}
+
 #[derive(Debug)]
 pub struct RegisteredMatrixKeys {
     pub keys: Vec<Key, REGISTERED_KEYS_ARRAY_SIZE>,
@@ -312,15 +384,20 @@ impl RegisteredMatrixKeys {
         }
     }
     /// Transform from Matrix to Hid keys
-    pub fn transform_matrix_to_hid(&mut self, layout: &Layout) {
-        // get the keycode for every keytype, except for combo keytype
+    ///
+    /// A key is resolved against the active layers until its press has been reported,
+    /// from then on the key is locked to the layer it was pressed on until it is released,
+    /// so a key always releases the same action it pressed, whatever layer changes happen in between
+    pub fn transform_matrix_to_hid(&mut self, layout: &Layout, layer_state: &LayerState) {
+        // get the action for every key, except for the combo actions
         self.keys.iter_mut().for_each(|key| {
-            match KeyType::check_type(&key.keycode) {
-                KeyType::Combo => { //skip this type
-                }
+            match key.action {
+                // skip the combos and the tap-hold keys, they are locked once registered
+                Action::Combo(_) | Action::ModTap(..) | Action::LayerTap(..) => {}
+                _ if key.info.reported => {}
                 _ => {
-                    key.keycode = layout.keymap[key.position.layer][key.position.row as usize]
-                        [key.position.col as usize];
+                    key.position.layer = layer_state.highest_layer();
+                    key.action = layout.get_action(&key.position, layer_state);
                 }
             }
         });
@@ -335,23 +412,17 @@ impl RegisteredMatrixKeys {
                 if let Some(index) = self
                     .keys
                     .iter_mut()
-                    .position(|registered_key| registered_key.position == *element)
+                    .position(|registered_key| registered_key.position.is_same_key(element))
                 {
-                    self.keys[index].info = KeyInfo {
-                        pressed_time: Instant::now(),
-                        state: KeyState::Pressed,
-                    };
+                    self.keys[index].refresh();
                 }
                 // else add it
                 else {
                     self.keys
                         .push(Key {
-                            keycode: Kc::Undf,
+                            action: Action::No,
                             position: *element,
-                            info: KeyInfo {
-                                pressed_time: Instant::now(),
-                                state: KeyState::Pressed,
-                            },
+                            info: KeyInfo::new(Instant::now()),
                         })
                         .expect("Registered matrix key Vec allocation full.");
                 }
@@ -368,7 +439,7 @@ impl RegisteredMatrixKeys {
     pub fn store_keys_slave(
         &mut self,
         slave_key_report: &Arc<Mutex<[u8; 6]>>,
-        layer: &Arc<Mutex<usize>>,
+        layer: &Arc<Mutex<LayerState>>,
     ) {
         // iter trough the received key report
         slave_key_report.lock().iter().for_each(|element| {
@@ -377,30 +448,24 @@ impl RegisteredMatrixKeys {
                 let slave_element_position = KeyPos {
                     row: *element >> BIT_SHIFT,
                     col: *element & 0x0F,
-                    layer: *layer.lock(),
+                    layer: layer.lock().highest_layer(),
                 };
 
                 // if the key is available in the vec, update it
                 if let Some(index) = self
                     .keys
                     .iter_mut()
-                    .position(|key| key.position == slave_element_position)
+                    .position(|key| key.position.is_same_key(&slave_element_position))
                 {
-                    self.keys[index].info = KeyInfo {
-                        pressed_time: Instant::now(),
-                        state: KeyState::Pressed,
-                    };
+                    self.keys[index].refresh();
                 }
                 // else add it
                 else {
                     self.keys
                         .push(Key {
-                            keycode: Kc::Undf,
+                            action: Action::No,
                             position: slave_element_position,
-                            info: KeyInfo {
-                                pressed_time: Instant::now(),
-                                state: KeyState::Pressed,
-                            },
+                            info: KeyInfo::new(Instant::now()),
                         })
                         .expect("Registered matrix key Vec allocation full.");
                 }
@@ -411,70 +476,114 @@ impl RegisteredMatrixKeys {
         });
     }
     /// Method for processing of combo keys - if feature enabled
-    pub fn process_combos(&mut self, layout: &Layout) {
-        for combo_dummy_keycode in layout.combos.iter() {
-            let (_combo_vec, combo_keys) = Kc::get_combo(combo_dummy_keycode);
-
-            let current_keys = self
-                .keys
-                .iter()
-                .map(|key| key.keycode)
-                .collect::<Vec<Kc, 12>>();
-
-            // check if the key combination matches
-            if combo_keys.iter().all(|combo_key| {
-                self.keys
-                    .iter()
-                    .any(|key| key.keycode == *combo_key && key.info.state == KeyState::Pressed)
+    ///
+    /// A combo is pressed when all of its keys are pressed within the combo term,
+    /// overlapping combos are resolved in favor of the combo with the most keys.
+    /// The first pressed key of the combo sends the combo action until it is released,
+    /// the other keys of the combo do nothing until they are released.
+    ///
+    /// Returns the count of the keys to process, the keys before the first one which may still become a combo
+    pub fn process_combos(&mut self, layout: &Layout, layer_state: &LayerState) -> usize {
+        let highest_layer = layer_state.highest_layer();
+        let max_combo_keys = layout
+            .combos
+            .iter()
+            .map(|combo| combo.keys.len())
+            .max()
+            .unwrap_or(0);
+
+        // keys which may still become part of a combo with more keys
+        let mut pending_keys: Vec<usize, REGISTERED_KEYS_ARRAY_SIZE> = Vec::new();
+        let mut held_back_index = self.keys.len();
+
+        // resolve the combos with the most keys first
+        for combo_key_count in (1..=max_combo_keys).rev() {
+            for combo in layout.combos.iter().filter(|combo| {
+                combo.keys.len() == combo_key_count
+                    && (combo.layers.is_empty() || combo.layers.contains(&highest_layer))
             }) {
-                let mut pressed_time = Instant::now();
-                let mut to_remove: Vec<usize, 12> = Vec::new();
-
-                // find the keycodes and add them to be removed from the originally pressed keys
-                for (index, keycode) in current_keys.iter().enumerate() {
-                    if combo_keys.contains(keycode) {
-                        pressed_time = self.keys[index].info.pressed_time;
-                        to_remove.push(index).unwrap();
+                let members = self.match_combo(combo);
+
+                let Some(first_pressed_time) = members
+                    .iter()
+                    .map(|index| self.keys[*index].info.first_pressed_time)
+                    .min()
+                else {
+                    continue;
+                };
+
+                let is_complete = members.len() == combo.keys.len()
+                    && members.iter().all(|index| {
+                        self.keys[*index].info.first_pressed_time <= first_pressed_time + COMBO_TERM
+                    });
+
+                if is_complete && !members.iter().any(|index| pending_keys.contains(index)) {
+                    // the first pressed key sends the action, the other keys do nothing
+                    for (position, index) in members.iter().enumerate() {
+                        self.keys[*index].action = if position == 0 {
+                            Action::Combo(combo.action)
+                        } else {
+                            Action::Combo(&[])
+                        };
+                    }
+                } else if is_complete || Instant::now() < first_pressed_time + COMBO_TERM {
+                    // wait for the rest of the keys, or for the overlapping combo to be resolved
+                    for index in members.iter() {
+                        if !pending_keys.contains(index) {
+                            let _ = pending_keys.push(*index);
+                        }
+                        held_back_index = held_back_index.min(*index);
                     }
                 }
-                // sort them
-                to_remove.sort();
+            }
+        }
 
-                // remove reversed
-                for index in to_remove.iter().rev() {
-                    let _ = self.keys.remove(*index);
-                }
+        held_back_index
+    }
 
-                // add new combo key to be processed
-                self.keys
-                    .push(Key {
-                        keycode: *combo_dummy_keycode,
-                        position: KeyPos::default(),
-                        info: KeyInfo {
-                            pressed_time,
-                            state: KeyState::Pressed,
-                        },
-                    })
-                    .unwrap();
-            } else if current_keys.contains(&combo_dummy_keycode) {
-                for (index, keycode) in current_keys.iter().enumerate() {
-                    if keycode != combo_dummy_keycode {
-                        self.keys.remove(index);
+    /// Match the combo keys with the pressed keys which are not sent yet
+    ///
+    /// Returns the indexes of the matched keys, in the order they have been pressed
+    fn match_combo(&self, combo: &Combo) -> Vec<usize, REGISTERED_KEYS_ARRAY_SIZE> {
+        let mut members: Vec<usize, REGISTERED_KEYS_ARRAY_SIZE> = Vec::new();
+
+        for combo_key in combo.keys.iter() {
+            if let Some(index) = (0..self.keys.len()).find(|index| {
+                let key = &self.keys[*index];
+
+                !members.contains(index)
+                    && key.info.state == KeyState::Pressed
+                    && !key.info.reported
+                    && !matches!(key.action, Action::Combo(_))
+                    && match combo_key {
+                        ComboKey::Pos(row, col) => {
+                            key.position.row == *row && key.position.col == *col
+                        }
+                        ComboKey::Key(action) => key.action == *action,
                     }
-                }
+            }) {
+                let _ = members.push(index);
             }
         }
+
+        members.sort_unstable();
+        members
     }
 }
 
 /// The main matrix scan function
+#[cfg(target_os = "espidf")]
 pub async fn scan_grid(
     registered_matrix_keys: &Arc<Mutex<RegisteredMatrixKeys>>,
-    layer: &Arc<Mutex<usize>>,
+    layer: &Arc<Mutex<LayerState>>,
     ble_status: &Arc<Mutex<BleStatus>>,
 ) -> ! {
-    // construct the matrix
-    let mut matrix = PinMatrix::new();
+    // construct the matrix, or the direct pins
+    #[cfg(not(feature = "direct-pins"))]
+    let mut matrix = esp::EspPinMatrix::new();
+
+    #[cfg(feature = "direct-pins")]
+    let mut matrix = esp::EspDirectPins::new();
 
     // local ble status variable
     let mut ble_status_local: BleStatus = BleStatus::NotConnected;
@@ -499,11 +608,7 @@ pub async fn scan_grid(
         // if a connection is established, run the key matrix
         match ble_status_local {
             BleStatus::Connected => {
-                #[cfg(feature = "async-scan")]
-                matrix.async_scan(registered_matrix_keys, layer).await;
-
-                #[cfg(not(feature = "async-scan"))]
-                matrix.standard_scan(registered_matrix_keys, layer).await;
+                matrix.scan(registered_matrix_keys, layer).await;
             }
             BleStatus::NotConnected => {
                 // sleep for 100ms
@@ -512,3 +617,288 @@ pub async fn scan_grid(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::sim::{scan, SimMatrix};
+    use super::*;
+    use crate::config::enums::Kc;
+    use embassy_time::MockDriver;
+
+    const ROW: u8 = 0;
+    const COL: u8 = 1;
+
+    fn test_layout() -> Layout {
+        let mut layout = Layout::default();
+        layout.keymap[0][ROW as usize][COL as usize] = Kc::A;
+        layout.keymap[1][ROW as usize][COL as usize] = Kc::B;
+        layout
+    }
+
+    fn press(registered_matrix_keys: &mut RegisteredMatrixKeys, layer_state: &LayerState) {
+        let mut registered_local_keys_array = [KeyPos::default(); 6];
+        registered_local_keys_array[0] = KeyPos::new(ROW, COL, layer_state.highest_layer());
+        registered_matrix_keys.store_keys_local(&mut registered_local_keys_array);
+    }
+
+    /// Mark the registered keys as sent, like the key provisioning does
+    fn report(registered_matrix_keys: &mut RegisteredMatrixKeys) {
+        registered_matrix_keys
+            .keys
+            .iter_mut()
+            .for_each(|key| key.info.reported = true);
+    }
+
+    #[test]
+    fn held_key_keeps_its_keycode_after_layer_change() {
+        let layout = test_layout();
+        let mut layer_state = LayerState::new(0);
+        let mut registered_matrix_keys = RegisteredMatrixKeys::new(ENTER_SLEEP_DEBOUNCE);
+
+        press(&mut registered_matrix_keys, &layer_state);
+        registered_matrix_keys.transform_matrix_to_hid(&layout, &layer_state);
+        report(&mut registered_matrix_keys);
+        assert_eq!(registered_matrix_keys.keys[0].action, Kc::A);
+
+        // the layer changes while the key is held
+        layer_state.on(1);
+        press(&mut registered_matrix_keys, &layer_state);
+        registered_matrix_keys.transform_matrix_to_hid(&layout, &layer_state);
+
+        assert_eq!(registered_matrix_keys.keys.len(), 1);
+        assert_eq!(registered_matrix_keys.keys[0].action, Kc::A);
+        assert_eq!(registered_matrix_keys.keys[0].position.layer, 0);
+
+        // and back, before the key is released
+        layer_state.off(1);
+        registered_matrix_keys.keys[0].info.state = KeyState::Released;
+        registered_matrix_keys.transform_matrix_to_hid(&layout, &layer_state);
+
+        assert_eq!(registered_matrix_keys.keys[0].action, Kc::A);
+    }
+
+    #[test]
+    fn only_tap_dance_keys_count_the_presses_before_their_removal() {
+        for (action, taps) in [(Kc::TD(0), 2), (Kc::A, 1)] {
+            let mut layout = Layout::default();
+            layout.keymap[0][ROW as usize][COL as usize] = action;
+            let layer_state = LayerState::new(0);
+            let mut registered_matrix_keys = RegisteredMatrixKeys::new(ENTER_SLEEP_DEBOUNCE);
+
+            press(&mut registered_matrix_keys, &layer_state);
+            registered_matrix_keys.transform_matrix_to_hid(&layout, &layer_state);
+
+            // pressed again while still registered
+            registered_matrix_keys.keys[0].info.state = KeyState::Released;
+            press(&mut registered_matrix_keys, &layer_state);
+
+            assert_eq!(registered_matrix_keys.keys[0].info.state, KeyState::Pressed);
+            assert_eq!(registered_matrix_keys.keys[0].info.taps, taps);
+        }
+    }
+
+    #[test]
+    fn key_pressed_after_layer_change_uses_the_new_layer() {
+        let layout = test_layout();
+        let mut layer_state = LayerState::new(0);
+        let mut registered_matrix_keys = RegisteredMatrixKeys::new(ENTER_SLEEP_DEBOUNCE);
+
+        layer_state.on(1);
+        press(&mut registered_matrix_keys, &layer_state);
+        registered_matrix_keys.transform_matrix_to_hid(&layout, &layer_state);
+        report(&mut registered_matrix_keys);
+
+        // the layer is released before the key
+        layer_state.off(1);
+        registered_matrix_keys.transform_matrix_to_hid(&layout, &layer_state);
+
+        assert_eq!(registered_matrix_keys.keys[0].action, Kc::B);
+        assert_eq!(registered_matrix_keys.keys[0].position.layer, 1);
+    }
+
+    #[test]
+    fn unreported_key_follows_layer_change() {
+        let layout = test_layout();
+        let mut layer_state = LayerState::new(0);
+        let mut registered_matrix_keys = RegisteredMatrixKeys::new(ENTER_SLEEP_DEBOUNCE);
+
+        press(&mut registered_matrix_keys, &layer_state);
+        registered_matrix_keys.transform_matrix_to_hid(&layout, &layer_state);
+        assert_eq!(registered_matrix_keys.keys[0].action, Kc::A);
+
+        // e.g. a key held back while a layer-tap key gets decided
+        layer_state.on(1);
+        registered_matrix_keys.transform_matrix_to_hid(&layout, &layer_state);
+
+        assert_eq!(registered_matrix_keys.keys[0].action, Kc::B);
+    }
+
+    #[test]
+    fn transparent_key_is_locked_once_reported() {
+        let mut layout = test_layout();
+        layout.keymap[1][ROW as usize][COL as usize] = Kc::Trns;
+        let mut layer_state = LayerState::new(0);
+        let mut registered_matrix_keys = RegisteredMatrixKeys::new(ENTER_SLEEP_DEBOUNCE);
+
+        layer_state.on(1);
+        press(&mut registered_matrix_keys, &layer_state);
+        registered_matrix_keys.transform_matrix_to_hid(&layout, &layer_state);
+        report(&mut registered_matrix_keys);
+        assert_eq!(registered_matrix_keys.keys[0].action, Kc::A);
+
+        // the key is not resolved again while held
+        layout.keymap[0][ROW as usize][COL as usize] = Kc::C;
+        layer_state.off(1);
+        registered_matrix_keys.transform_matrix_to_hid(&layout, &layer_state);
+
+        assert_eq!(registered_matrix_keys.keys[0].action, Kc::A);
+    }
+
+    #[test]
+    fn scan_registers_the_row_and_col_of_the_pressed_key() {
+        for (row, col) in [
+            (0, 0),
+            (0, COLS - 1),
+            (1, 3),
+            (ROWS - 1, 2),
+            (ROWS - 1, COLS - 1),
+        ] {
+            let sim_matrix = SimMatrix::default();
+            let mut matrix = sim_matrix.pin_matrix();
+            sim_matrix.press(row, col);
+
+            let positions = scan(&mut matrix, LayerState::new(0));
+
+            assert_eq!(
+                positions.as_slice(),
+                &[KeyPos::new(row as u8, col as u8 + COL_OFFSET, 0)]
+            );
+        }
+    }
+
+    #[test]
+    fn scan_registers_several_keys() {
+        let sim_matrix = SimMatrix::default();
+        let mut matrix = sim_matrix.pin_matrix();
+        let pressed = [
+            KeyPos::new(ROWS as u8 - 1, COL_OFFSET, 0),
+            KeyPos::new(0, COLS as u8 - 1 + COL_OFFSET, 0),
+            KeyPos::new(1, 2 + COL_OFFSET, 0),
+            KeyPos::new(1, 4 + COL_OFFSET, 0),
+            KeyPos::new(2, 2 + COL_OFFSET, 0),
+        ];
+        for position in pressed.iter() {
+            sim_matrix.press(position.row as usize, (position.col - COL_OFFSET) as usize);
+        }
+
+        let positions = scan(&mut matrix, LayerState::new(0));
+
+        // the scan order depends on the diode direction
+        assert_eq!(positions.len(), pressed.len());
+        assert!(pressed.iter().all(|position| positions.contains(position)));
+    }
+
+    #[test]
+    fn scan_skips_the_released_keys() {
+        let sim_matrix = SimMatrix::default();
+        let mut matrix = sim_matrix.pin_matrix();
+        sim_matrix.press(2, 1);
+        sim_matrix.press(2, 5);
+        sim_matrix.release(2, 1);
+
+        let positions = scan(&mut matrix, LayerState::new(0));
+
+        assert_eq!(positions.as_slice(), &[KeyPos::new(2, 5 + COL_OFFSET, 0)]);
+    }
+
+    #[test]
+    fn scan_stores_the_highest_layer() {
+        let sim_matrix = SimMatrix::default();
+        let mut matrix = sim_matrix.pin_matrix();
+        sim_matrix.press(0, 1);
+
+        let mut layer_state = LayerState::new(0);
+        layer_state.on(1);
+        let positions = scan(&mut matrix, layer_state);
+
+        assert_eq!(positions.as_slice(), &[KeyPos::new(0, 1 + COL_OFFSET, 1)]);
+    }
+
+    const COMBOS: &[Combo] = &[Combo {
+        keys: &[ComboKey::Key(Kc::A), ComboKey::Key(Kc::B)],
+        action: &[Kc::C],
+        layers: &[],
+    }];
+
+    fn combo_key(action: Action, col: u8) -> Key {
+        Key {
+            action,
+            position: KeyPos::new(ROW, col, 0),
+            info: KeyInfo::new(Instant::now()),
+        }
+    }
+
+    fn combo_layout() -> Layout {
+        Layout {
+            combos: COMBOS,
+            ..Layout::default()
+        }
+    }
+
+    #[test]
+    fn combo_keys_pressed_within_the_combo_term_send_the_combo_action() {
+        let _time = sim::mock_time();
+        let mut registered_matrix_keys = RegisteredMatrixKeys::new(ENTER_SLEEP_DEBOUNCE);
+        let _ = registered_matrix_keys.keys.push(combo_key(Kc::A, 1));
+
+        MockDriver::get().advance(COMBO_TERM);
+        let _ = registered_matrix_keys.keys.push(combo_key(Kc::B, 2));
+
+        let processed_keys =
+            registered_matrix_keys.process_combos(&combo_layout(), &LayerState::new(0));
+
+        assert_eq!(processed_keys, 2);
+        assert_eq!(
+            registered_matrix_keys.keys[0].action,
+            Action::Combo(&[Kc::C])
+        );
+        assert_eq!(registered_matrix_keys.keys[1].action, Action::Combo(&[]));
+    }
+
+    #[test]
+    fn combo_key_is_held_back_until_the_combo_term_has_passed() {
+        let _time = sim::mock_time();
+        let mut registered_matrix_keys = RegisteredMatrixKeys::new(ENTER_SLEEP_DEBOUNCE);
+        let _ = registered_matrix_keys.keys.push(combo_key(Kc::A, 1));
+
+        MockDriver::get().advance(COMBO_TERM - Duration::from_millis(1));
+        assert_eq!(
+            registered_matrix_keys.process_combos(&combo_layout(), &LayerState::new(0)),
+            0
+        );
+
+        MockDriver::get().advance(Duration::from_millis(1));
+        assert_eq!(
+            registered_matrix_keys.process_combos(&combo_layout(), &LayerState::new(0)),
+            1
+        );
+        assert_eq!(registered_matrix_keys.keys[0].action, Kc::A);
+    }
+
+    #[test]
+    fn combo_key_pressed_after_the_combo_term_is_a_regular_key() {
+        let _time = sim::mock_time();
+        let mut registered_matrix_keys = RegisteredMatrixKeys::new(ENTER_SLEEP_DEBOUNCE);
+        let _ = registered_matrix_keys.keys.push(combo_key(Kc::A, 1));
+
+        MockDriver::get().advance(COMBO_TERM + Duration::from_millis(1));
+        let _ = registered_matrix_keys.keys.push(combo_key(Kc::B, 2));
+
+        let processed_keys =
+            registered_matrix_keys.process_combos(&combo_layout(), &LayerState::new(0));
+
+        assert_eq!(processed_keys, 2);
+        assert_eq!(registered_matrix_keys.keys[0].action, Kc::A);
+        assert_eq!(registered_matrix_keys.keys[1].action, Kc::B);
+    }
+}
@@ -0,0 +1,286 @@
+use super::{KeyPos, KeyScanner, PinMatrix, RegisteredMatrixKeys};
+use crate::config::user_config::{COLS, ENTER_SLEEP_DEBOUNCE, REGISTERED_KEYS_ARRAY_SIZE, ROWS};
+use crate::layer::LayerState;
+use crate::mutex::Mutex;
+
+#[cfg(feature = "direct-pins")]
+use core::cell::Cell;
+use core::cell::RefCell;
+use core::convert::Infallible;
+use core::future::Future;
+use core::pin::pin;
+use core::task::Poll;
+use embassy_time::{Duration, MockDriver};
+#[cfg(feature = "direct-pins")]
+use embedded_hal::digital::PinState;
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+use embedded_hal_async::digital::Wait;
+use heapless::Vec;
+use std::sync::MutexGuard;
+
+extern crate alloc;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+
+/// The mock time driver is shared by all of the tests,
+/// so the tests depending on the time run one at a time, each from the time 0
+pub(crate) fn mock_time() -> MutexGuard<'static, ()> {
+    static MOCK_TIME: Mutex<()> = Mutex::new(());
+
+    let guard = MOCK_TIME.lock();
+    MockDriver::get().reset();
+    guard
+}
+
+/// Run the future to completion, the mock time is advanced while the future waits
+fn run<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+
+    loop {
+        if let Poll::Ready(output) = embassy_futures::poll_once(future.as_mut()) {
+            return output;
+        }
+        MockDriver::get().advance(Duration::from_micros(100));
+    }
+}
+
+/// Scan the simulated switches once and return the registered key positions
+pub(super) fn scan(
+    scanner: &mut impl KeyScanner,
+    layer_state: LayerState,
+) -> Vec<KeyPos, REGISTERED_KEYS_ARRAY_SIZE> {
+    let _time = mock_time();
+    let registered_matrix_keys =
+        Arc::new(Mutex::new(RegisteredMatrixKeys::new(ENTER_SLEEP_DEBOUNCE)));
+    let layer = Arc::new(Mutex::new(layer_state));
+
+    run(scanner.scan(&registered_matrix_keys, &layer));
+
+    let positions = registered_matrix_keys
+        .lock()
+        .keys
+        .iter()
+        .map(|key| key.position)
+        .collect();
+    positions
+}
+
+#[derive(Default)]
+struct Switches {
+    pressed: [[bool; COLS]; ROWS],
+    driven_rows: [bool; ROWS],
+    driven_cols: [bool; COLS],
+}
+
+impl Switches {
+    fn drive(&mut self, line: Line, high: bool) {
+        match line {
+            Line::Row(row) => self.driven_rows[row] = high,
+            Line::Col(col) => self.driven_cols[col] = high,
+        }
+    }
+
+    /// A line is high while a pressed switch connects it to a driven line
+    fn is_high(&self, line: Line) -> bool {
+        match line {
+            Line::Row(row) => (0..COLS).any(|col| self.driven_cols[col] && self.pressed[row][col]),
+            Line::Col(col) => (0..ROWS).any(|row| self.driven_rows[row] && self.pressed[row][col]),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Line {
+    Row(usize),
+    Col(usize),
+}
+
+/// Simulated key matrix, so the scanning can be tested on the host
+///
+/// The switches are shared by all of the pins,
+/// an input pin reads high while a pressed switch connects it to a driven output pin
+#[derive(Clone, Default)]
+pub struct SimMatrix {
+    switches: Rc<RefCell<Switches>>,
+}
+
+impl SimMatrix {
+    pub fn press(&self, row: usize, col: usize) {
+        self.switches.borrow_mut().pressed[row][col] = true;
+    }
+
+    pub fn release(&self, row: usize, col: usize) {
+        self.switches.borrow_mut().pressed[row][col] = false;
+    }
+
+    /// Construct a pin matrix wired to the simulated switches, in the configured diode direction
+    pub fn pin_matrix(&self) -> PinMatrix<SimOutputPin, SimInputPin> {
+        PinMatrix::from_lines(
+            core::array::from_fn(Line::Row),
+            core::array::from_fn(Line::Col),
+            |line| SimOutputPin {
+                line,
+                switches: Rc::clone(&self.switches),
+            },
+            |line| SimInputPin {
+                line,
+                switches: Rc::clone(&self.switches),
+            },
+        )
+    }
+}
+
+pub struct SimOutputPin {
+    line: Line,
+    switches: Rc<RefCell<Switches>>,
+}
+
+impl ErrorType for SimOutputPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for SimOutputPin {
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.switches.borrow_mut().drive(self.line, true);
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.switches.borrow_mut().drive(self.line, false);
+        Ok(())
+    }
+}
+
+pub struct SimInputPin {
+    line: Line,
+    switches: Rc<RefCell<Switches>>,
+}
+
+impl ErrorType for SimInputPin {
+    type Error = Infallible;
+}
+
+impl InputPin for SimInputPin {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.switches.borrow().is_high(self.line))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.switches.borrow().is_high(self.line))
+    }
+}
+
+/// The switches do not change while a scan waits on them,
+/// so a level that is not reached right away is never reached
+async fn wait_for_level(reached: bool) -> Result<(), Infallible> {
+    if !reached {
+        core::future::pending::<()>().await;
+    }
+    Ok(())
+}
+
+impl Wait for SimInputPin {
+    async fn wait_for_high(&mut self) -> Result<(), Infallible> {
+        wait_for_level(self.is_high()?).await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Infallible> {
+        wait_for_level(self.is_low()?).await
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Infallible> {
+        core::future::pending().await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Infallible> {
+        core::future::pending().await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Infallible> {
+        core::future::pending().await
+    }
+}
+
+/// Simulated switch wired straight to a pin, the pin reads the active level while pressed
+#[cfg(feature = "direct-pins")]
+pub struct SimSwitch {
+    pressed: Rc<Cell<bool>>,
+    active: PinState,
+}
+
+#[cfg(feature = "direct-pins")]
+impl SimSwitch {
+    pub fn new(active: PinState) -> Self {
+        Self {
+            pressed: Rc::new(Cell::new(false)),
+            active,
+        }
+    }
+
+    pub fn press(&self) {
+        self.pressed.set(true);
+    }
+
+    pub fn active(&self) -> PinState {
+        self.active
+    }
+
+    pub fn pin(&self) -> SimSwitchPin {
+        SimSwitchPin {
+            pressed: Rc::clone(&self.pressed),
+            active: self.active,
+        }
+    }
+}
+
+#[cfg(feature = "direct-pins")]
+pub struct SimSwitchPin {
+    pressed: Rc<Cell<bool>>,
+    active: PinState,
+}
+
+#[cfg(feature = "direct-pins")]
+impl SimSwitchPin {
+    fn level(&self) -> bool {
+        self.pressed.get() == (self.active == PinState::High)
+    }
+}
+
+#[cfg(feature = "direct-pins")]
+impl ErrorType for SimSwitchPin {
+    type Error = Infallible;
+}
+
+#[cfg(feature = "direct-pins")]
+impl InputPin for SimSwitchPin {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.level())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.level())
+    }
+}
+
+#[cfg(feature = "direct-pins")]
+impl Wait for SimSwitchPin {
+    async fn wait_for_high(&mut self) -> Result<(), Infallible> {
+        wait_for_level(self.level()).await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Infallible> {
+        wait_for_level(!self.level()).await
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Infallible> {
+        core::future::pending().await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Infallible> {
+        core::future::pending().await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Infallible> {
+        core::future::pending().await
+    }
+}
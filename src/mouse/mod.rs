@@ -1,7 +1,24 @@
 use crate::config::{
-    enums::{HidMouseKeys, Kc},
-    user_config::{CURSOR_PARAM_FAST, CURSOR_PARAM_NORMAL, CURSOR_PARAM_SLOW},
+    enums::{HidMouseKeys, MouseAction},
+    user_config::{
+        MOUSEKEY_CURVE, MOUSEKEY_DELAY, MOUSEKEY_INTERVAL, MOUSEKEY_MAX_SPEED, MOUSEKEY_MOVE_DELTA,
+        MOUSEKEY_SLOW_SPEED, MOUSEKEY_TIME_TO_MAX, MOUSEKEY_WHEEL_DELAY, MOUSEKEY_WHEEL_INTERVAL,
+        MOUSEKEY_WHEEL_MAX_SPEED, MOUSEKEY_WHEEL_RESOLUTION, MOUSEKEY_WHEEL_SPEED,
+        MOUSEKEY_WHEEL_TIME_TO_MAX, MOUSE_WARP_TIMEOUT,
+    },
 };
+use embassy_time::{Duration, Instant};
+
+/// Acceleration curve of the cursor and the wheel, from the start speed to the max speed
+#[derive(Clone, Copy, PartialEq)]
+pub enum AccelerationCurve {
+    /// always moves with the move delta
+    Constant,
+    /// the speed grows evenly over the time to max
+    Linear,
+    /// the speed grows slowly at first, for precise short moves
+    Quadratic,
+}
 
 #[derive(Default, Clone, Copy, PartialEq)]
 enum CursorSpeed {
@@ -11,114 +28,615 @@ enum CursorSpeed {
     Slow,
 }
 
+/// Logical maximum of the absolute pointer coordinates
+pub const ABSOLUTE_POINTER_MAX: u16 = 0x7FFF;
+
+/// Keyboard driven pointer warp, in the spirit of keynav
+///
+/// The first warp key jumps the pointer to the center of a cell of the 3x3 grid over the whole screen,
+/// the next warp keys split that cell into a new 3x3 grid, so every key narrows the region.
+/// A warp key pressed after the warp timeout, or after a warp reset, starts from the whole screen again.
+#[derive(Default, Clone, Copy, PartialEq)]
+struct PointerWarp {
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+    held: u16,
+    last_warp: Option<Instant>,
+    warped: bool,
+}
+
+impl PointerWarp {
+    /// Narrow the region to the cell of the pressed warp key, once per key press
+    fn press(&mut self, cell: u8) {
+        if cell > 8 || self.held & (1 << cell) != 0 {
+            return;
+        }
+        self.held |= 1 << cell;
+
+        let now = Instant::now();
+        if self
+            .last_warp
+            .is_none_or(|last_warp| now >= last_warp + MOUSE_WARP_TIMEOUT)
+        {
+            self.reset();
+        }
+
+        self.width /= 3;
+        self.height /= 3;
+        self.x += self.width * (cell % 3) as u16;
+        self.y += self.height * (cell / 3) as u16;
+
+        self.last_warp = Some(now);
+        self.warped = true;
+    }
+
+    fn release(&mut self, cell: u8) {
+        if cell <= 8 {
+            self.held &= !(1 << cell);
+        }
+    }
+
+    /// Start the next warp from the whole screen
+    fn reset(&mut self) {
+        self.x = 0;
+        self.y = 0;
+        self.width = ABSOLUTE_POINTER_MAX;
+        self.height = ABSOLUTE_POINTER_MAX;
+    }
+
+    /// Pointer position, the center of the current region
+    fn position(&self) -> (u16, u16) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+}
+
 #[derive(Default, Clone, Copy, PartialEq)]
 pub struct MouseKeyReport {
     buttons: u8,
-    x: u8,
-    y: u8,
-    v_wheel: u8,
-    h_wheel: u8,
+    locked_buttons: u8,
+    x: i8,
+    y: i8,
+    v_wheel: i8,
+    h_wheel: i8,
+    held: u8,
     speed: CursorSpeed,
+    move_start: Option<Instant>,
+    next_move: Option<Instant>,
+    wheel_start: Option<Instant>,
+    next_wheel: Option<Instant>,
+    v_wheel_remainder: u64,
+    h_wheel_remainder: u64,
+    wheel_resolution: u8,
+    warp: PointerWarp,
+    pointer_warped: bool,
 }
 
 impl MouseKeyReport {
+    // held direction and wheel keys
+    const LEFT: u8 = 0x01;
+    const RIGHT: u8 = 0x02;
+    const UP: u8 = 0x04;
+    const DOWN: u8 = 0x08;
+    const SCROLL_LEFT: u8 = 0x10;
+    const SCROLL_RIGHT: u8 = 0x20;
+    const SCROLL_UP: u8 = 0x40;
+    const SCROLL_DOWN: u8 = 0x80;
+
+    const CURSOR: u8 = Self::LEFT | Self::RIGHT | Self::UP | Self::DOWN;
+    const WHEEL: u8 = Self::SCROLL_LEFT | Self::SCROLL_RIGHT | Self::SCROLL_UP | Self::SCROLL_DOWN;
+
+    // resolution multiplier feature report
+    const V_WHEEL_HIGH_RESOLUTION: u8 = 0x03;
+    const H_WHEEL_HIGH_RESOLUTION: u8 = 0x0C;
+
     /// Store the struct in an array that is ready to be sent
     pub fn construct(self) -> [u8; 5] {
-        [self.buttons, self.x, self.y, self.v_wheel, self.h_wheel]
-    }
-
-    /// Translate the HidKey to a mouse command
-    pub fn set_command(&mut self, valid_key: &Kc) {
-        match *valid_key {
-            Kc::MoGL => self.go_left(),
-            Kc::MoGD => self.go_down(),
-            Kc::MoGU => self.go_up(),
-            Kc::MoGR => self.go_right(),
-            Kc::MoLC => self.click(HidMouseKeys::LeftClick),
-            Kc::MoRC => self.click(HidMouseKeys::RightClick),
-            Kc::MoSL => self.scroll_left(),
-            Kc::MoSR => self.scroll_right(),
-            Kc::MoSU => self.scroll_up(),
-            Kc::MoSD => self.scroll_down(),
-            Kc::MoCF => self.speed = CursorSpeed::Fast,
-            Kc::MoCN => self.speed = CursorSpeed::Normal,
-            Kc::MoCS => self.speed = CursorSpeed::Slow,
-
-            _ => {} // do nothing
+        [
+            self.buttons(),
+            self.x as u8,
+            self.y as u8,
+            self.v_wheel as u8,
+            self.h_wheel as u8,
+        ]
+    }
+
+    /// Store the absolute pointer position in an array that is ready to be sent,
+    /// the buttons are sent in the relative report only
+    pub fn construct_absolute(self) -> [u8; 5] {
+        let (x, y) = self.warp.position();
+        let (x, y) = (x.to_le_bytes(), y.to_le_bytes());
+
+        [0, x[0], x[1], y[0], y[1]]
+    }
+
+    /// Translate the mouse action to a mouse command, on every pass while the key is held
+    ///
+    /// The drag lock flips the locked buttons once, in `reset_keypress`
+    pub fn set_command(&mut self, mouse_action: &MouseAction) {
+        match *mouse_action {
+            MouseAction::GoLeft => self.held |= Self::LEFT,
+            MouseAction::GoDown => self.held |= Self::DOWN,
+            MouseAction::GoUp => self.held |= Self::UP,
+            MouseAction::GoRight => self.held |= Self::RIGHT,
+            MouseAction::LeftClick => self.click(HidMouseKeys::LeftClick),
+            MouseAction::RightClick => self.click(HidMouseKeys::RightClick),
+            MouseAction::MiddleClick => self.click(HidMouseKeys::MiddleClick),
+            MouseAction::BackClick => self.click(HidMouseKeys::Back),
+            MouseAction::ForwardClick => self.click(HidMouseKeys::Forward),
+            MouseAction::DragLock(_) => {}
+            MouseAction::ScrollLeft => self.held |= Self::SCROLL_LEFT,
+            MouseAction::ScrollRight => self.held |= Self::SCROLL_RIGHT,
+            MouseAction::ScrollUp => self.held |= Self::SCROLL_UP,
+            MouseAction::ScrollDown => self.held |= Self::SCROLL_DOWN,
+            MouseAction::CursorFast => self.speed = CursorSpeed::Fast,
+            MouseAction::CursorNormal => self.speed = CursorSpeed::Normal,
+            MouseAction::CursorSlow => self.speed = CursorSpeed::Slow,
+            MouseAction::Warp(cell) => self.warp.press(cell),
+            MouseAction::WarpReset => self.warp.last_warp = None,
         }
     }
 
     /// Reset last pressed mouse key
-    pub fn reset_keypress(&mut self, valid_key: &Kc) {
-        match *valid_key {
-            Kc::MoGL | Kc::MoGR => self.x = 0,
-            Kc::MoGD | Kc::MoGU => self.y = 0,
-            Kc::MoLC | Kc::MoRC => self.buttons = 0,
-            Kc::MoSU | Kc::MoSD => self.v_wheel = 0,
-            Kc::MoSL | Kc::MoSR => self.h_wheel = 0,
-            Kc::MoCF | Kc::MoCS => self.speed = CursorSpeed::Normal,
-
-            _ => {} // do nothing
+    pub fn reset_keypress(&mut self, mouse_action: &MouseAction) {
+        match *mouse_action {
+            MouseAction::GoLeft => self.held &= !Self::LEFT,
+            MouseAction::GoRight => self.held &= !Self::RIGHT,
+            MouseAction::GoDown => self.held &= !Self::DOWN,
+            MouseAction::GoUp => self.held &= !Self::UP,
+            MouseAction::LeftClick => self.release(HidMouseKeys::LeftClick),
+            MouseAction::RightClick => self.release(HidMouseKeys::RightClick),
+            MouseAction::MiddleClick => self.release(HidMouseKeys::MiddleClick),
+            MouseAction::BackClick => self.release(HidMouseKeys::Back),
+            MouseAction::ForwardClick => self.release(HidMouseKeys::Forward),
+            MouseAction::DragLock(button) => self.locked_buttons ^= button as u8,
+            MouseAction::ScrollUp => self.held &= !Self::SCROLL_UP,
+            MouseAction::ScrollDown => self.held &= !Self::SCROLL_DOWN,
+            MouseAction::ScrollLeft => self.held &= !Self::SCROLL_LEFT,
+            MouseAction::ScrollRight => self.held &= !Self::SCROLL_RIGHT,
+            MouseAction::CursorFast | MouseAction::CursorSlow => self.speed = CursorSpeed::Normal,
+
+            MouseAction::Warp(cell) => self.warp.release(cell),
+
+            MouseAction::CursorNormal | MouseAction::WarpReset => {} // do nothing
         }
     }
-    // Reset mouse report
-    pub fn reset_report(&mut self) {
-        self.buttons = 0;
+
+    /// check if cursor position is changed
+    pub fn is_cursor_position_changed(&mut self) -> bool {
+        (self.x | self.y != 0) || (self.v_wheel | self.h_wheel != 0)
+    }
+
+    /// check if the pointer has been warped to a new absolute position
+    pub fn is_pointer_warped(&self) -> bool {
+        self.pointer_warped
+    }
+
+    /// Get the pressed and the drag locked mouse buttons
+    pub fn buttons(&self) -> u8 {
+        self.buttons | self.locked_buttons
+    }
+
+    /// Set the wheel resolution from the resolution multiplier feature report written by the host
+    pub fn set_wheel_resolution(&mut self, resolution_multiplier: u8) {
+        self.wheel_resolution = resolution_multiplier;
+    }
+
+    /// Calculate the cursor and the wheel movement of this report from the held keys
+    ///
+    /// The first move is sent right away, the next ones after the mouse key delay,
+    /// then every mouse key interval, with the speed taken from the acceleration curve
+    pub fn update(&mut self) {
+        let now = Instant::now();
+
         self.x = 0;
         self.y = 0;
         self.v_wheel = 0;
         self.h_wheel = 0;
-    }
 
-    /// check if cursor position is changed
-    pub fn is_cursor_position_changed(&mut self) -> bool {
-        (self.x | self.y != 0) || (self.v_wheel | self.h_wheel != 0)
+        // the absolute position is sent once per warp
+        self.pointer_warped = core::mem::take(&mut self.warp.warped);
+
+        if self.held & Self::CURSOR != 0 {
+            let move_start = *self.move_start.get_or_insert(now);
+
+            if Self::is_due(self.next_move, now) {
+                let step = self.step(now.duration_since(move_start).as_millis());
+                let (x, y) = (
+                    Self::direction(self.held, Self::LEFT, Self::RIGHT),
+                    Self::direction(self.held, Self::UP, Self::DOWN),
+                );
+
+                // keep the speed of the diagonal moves, step * 1/sqrt(2)
+                let step = if x != 0 && y != 0 {
+                    (step as u16 * 181 / 256).max(1) as i8
+                } else {
+                    step as i8
+                };
+
+                self.x = x * step;
+                self.y = y * step;
+
+                self.next_move = Some(match self.next_move {
+                    None => now + MOUSEKEY_DELAY,
+                    Some(_) => now + MOUSEKEY_INTERVAL,
+                });
+            }
+        } else {
+            self.move_start = None;
+            self.next_move = None;
+        }
+
+        if self.held & Self::WHEEL != 0 {
+            let wheel_start = *self.wheel_start.get_or_insert(now);
+
+            if Self::is_due(self.next_wheel, now) {
+                let first = self.next_wheel.is_none();
+
+                // wheel speed in notches per second
+                let speed = accelerate(
+                    MOUSEKEY_CURVE,
+                    now.duration_since(wheel_start)
+                        .as_millis()
+                        .saturating_sub(MOUSEKEY_WHEEL_DELAY.as_millis()),
+                    MOUSEKEY_WHEEL_SPEED as u64,
+                    MOUSEKEY_WHEEL_MAX_SPEED as u64,
+                    MOUSEKEY_WHEEL_TIME_TO_MAX,
+                );
+
+                self.v_wheel = Self::wheel_step(
+                    Self::direction(self.held, Self::SCROLL_UP, Self::SCROLL_DOWN),
+                    self.wheel_units(Self::V_WHEEL_HIGH_RESOLUTION),
+                    &mut self.v_wheel_remainder,
+                    speed,
+                    first,
+                );
+                self.h_wheel = Self::wheel_step(
+                    Self::direction(self.held, Self::SCROLL_LEFT, Self::SCROLL_RIGHT),
+                    self.wheel_units(Self::H_WHEEL_HIGH_RESOLUTION),
+                    &mut self.h_wheel_remainder,
+                    speed,
+                    first,
+                );
+
+                self.next_wheel = Some(match self.next_wheel {
+                    None => now + MOUSEKEY_WHEEL_DELAY,
+                    Some(_) => now + MOUSEKEY_WHEEL_INTERVAL,
+                });
+            }
+        } else {
+            self.wheel_start = None;
+            self.next_wheel = None;
+        }
     }
 
-    fn go_left(&mut self) {
-        match self.speed {
-            CursorSpeed::Fast => self.x = 255 - CURSOR_PARAM_FAST,
-            CursorSpeed::Normal => self.x = 255 - CURSOR_PARAM_NORMAL,
-            CursorSpeed::Slow => self.x = 255 - CURSOR_PARAM_SLOW,
+    /// Wheel units of a notch, more than one if the host has enabled the high resolution
+    fn wheel_units(&self, high_resolution: u8) -> u64 {
+        if self.wheel_resolution & high_resolution != 0 {
+            MOUSEKEY_WHEEL_RESOLUTION as u64
+        } else {
+            1
         }
     }
-    fn go_right(&mut self) {
-        match self.speed {
-            CursorSpeed::Fast => self.x = 1 + CURSOR_PARAM_FAST,
-            CursorSpeed::Normal => self.x = 1 + CURSOR_PARAM_NORMAL,
-            CursorSpeed::Slow => self.x = 1 + CURSOR_PARAM_SLOW,
+
+    /// Wheel step of one scroll report
+    ///
+    /// The first step scrolls a whole notch, so a tapped key always scrolls,
+    /// the next ones scroll with the wheel speed, the fractions of a unit are carried over
+    fn wheel_step(direction: i8, units: u64, remainder: &mut u64, speed: u64, first: bool) -> i8 {
+        if direction == 0 {
+            *remainder = 0;
+            return 0;
         }
+
+        let step = if first {
+            *remainder = 0;
+            units
+        } else {
+            // thousandths of a wheel unit scrolled within the interval
+            *remainder += speed * units * MOUSEKEY_WHEEL_INTERVAL.as_millis();
+            let step = *remainder / 1000;
+            *remainder %= 1000;
+            step
+        };
+
+        direction * step.min(i8::MAX as u64) as i8
     }
-    fn go_up(&mut self) {
+
+    /// Cursor step of one move, after the keys have been held for the elapsed milliseconds
+    fn step(&self, elapsed: u64) -> u8 {
         match self.speed {
-            CursorSpeed::Fast => self.y = 255 - CURSOR_PARAM_FAST,
-            CursorSpeed::Normal => self.y = 255 - CURSOR_PARAM_NORMAL,
-            CursorSpeed::Slow => self.y = 255 - CURSOR_PARAM_SLOW,
+            CursorSpeed::Fast => MOUSEKEY_MAX_SPEED,
+            CursorSpeed::Slow => MOUSEKEY_SLOW_SPEED,
+            CursorSpeed::Normal => accelerate(
+                MOUSEKEY_CURVE,
+                elapsed.saturating_sub(MOUSEKEY_DELAY.as_millis()),
+                MOUSEKEY_MOVE_DELTA as u64,
+                MOUSEKEY_MAX_SPEED as u64,
+                MOUSEKEY_TIME_TO_MAX,
+            ) as u8,
         }
     }
-    fn go_down(&mut self) {
-        match self.speed {
-            CursorSpeed::Fast => self.y = 1 + CURSOR_PARAM_FAST,
-            CursorSpeed::Normal => self.y = 1 + CURSOR_PARAM_NORMAL,
-            CursorSpeed::Slow => self.y = 1 + CURSOR_PARAM_SLOW,
+
+    /// Check if the next move is due, the first move is sent right away
+    fn is_due(next: Option<Instant>, now: Instant) -> bool {
+        match next {
+            Some(next) => now >= next,
+            None => true,
         }
     }
 
-    // Method for sending the key pressed
+    /// Direction of the axis, -1, 0 or 1, opposite keys held together cancel out
+    fn direction(held: u8, negative: u8, positive: u8) -> i8 {
+        (held & positive != 0) as i8 - (held & negative != 0) as i8
+    }
+
+    // Method for sending the key pressed, the buttons are independent bits
     fn click(&mut self, button: HidMouseKeys) {
-        self.buttons = button as u8;
+        // clicking a drag locked button ends the drag, it is released with the click
+        self.locked_buttons &= !(button as u8);
+        self.buttons |= button as u8;
+    }
+
+    // Method for releasing the key pressed, the other buttons stay pressed
+    fn release(&mut self, button: HidMouseKeys) {
+        self.buttons &= !(button as u8);
+    }
+}
+
+/// Speed after accelerating for the elapsed milliseconds, following the acceleration curve
+fn accelerate(
+    curve: AccelerationCurve,
+    elapsed: u64,
+    start_speed: u64,
+    max_speed: u64,
+    time_to_max: Duration,
+) -> u64 {
+    let time_to_max = time_to_max.as_millis().max(1);
+    let elapsed = elapsed.min(time_to_max);
+    let range = max_speed.saturating_sub(start_speed);
+
+    let acceleration = match curve {
+        AccelerationCurve::Constant => 0,
+        AccelerationCurve::Linear => range * elapsed / time_to_max,
+        AccelerationCurve::Quadratic => range * elapsed * elapsed / (time_to_max * time_to_max),
+    };
+
+    start_speed + acceleration
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::sim::mock_time;
+    use embassy_time::MockDriver;
+
+    const TIME_TO_MAX: Duration = Duration::from_millis(800);
+
+    fn speeds(curve: AccelerationCurve) -> [u64; 4] {
+        [0, 400, 800, 1600].map(|elapsed| accelerate(curve, elapsed, 2, 16, TIME_TO_MAX))
     }
 
-    fn scroll_left(&mut self) {
-        self.h_wheel = 255;
+    fn held(keys: u8, speed: CursorSpeed) -> MouseKeyReport {
+        MouseKeyReport {
+            held: keys,
+            speed,
+            ..Default::default()
+        }
     }
-    fn scroll_right(&mut self) {
-        self.h_wheel = 1;
+
+    #[test]
+    fn constant_curve_keeps_the_start_speed() {
+        assert_eq!(speeds(AccelerationCurve::Constant), [2, 2, 2, 2]);
     }
-    fn scroll_up(&mut self) {
-        self.v_wheel = 255;
+
+    #[test]
+    fn linear_curve_reaches_half_the_range_halfway() {
+        assert_eq!(speeds(AccelerationCurve::Linear), [2, 9, 16, 16]);
     }
-    fn scroll_down(&mut self) {
-        self.v_wheel = 1;
+
+    #[test]
+    fn quadratic_curve_reaches_a_quarter_of_the_range_halfway() {
+        assert_eq!(speeds(AccelerationCurve::Quadratic), [2, 5, 16, 16]);
+    }
+
+    #[test]
+    fn zero_time_to_max_is_the_max_speed_right_away() {
+        assert_eq!(
+            accelerate(
+                AccelerationCurve::Linear,
+                1,
+                2,
+                16,
+                Duration::from_millis(0)
+            ),
+            16
+        );
+    }
+
+    #[test]
+    fn straight_move_uses_the_whole_step() {
+        let _time = mock_time();
+        let mut report = held(MouseKeyReport::RIGHT, CursorSpeed::Fast);
+
+        report.update();
+        assert_eq!((report.x, report.y), (MOUSEKEY_MAX_SPEED as i8, 0));
+    }
+
+    #[test]
+    fn diagonal_move_scales_the_step_by_one_over_the_square_root_of_two() {
+        let _time = mock_time();
+        let mut report = held(
+            MouseKeyReport::LEFT | MouseKeyReport::DOWN,
+            CursorSpeed::Fast,
+        );
+
+        report.update();
+        let step = (MOUSEKEY_MAX_SPEED as u16 * 181 / 256) as i8;
+        assert_eq!((report.x, report.y), (-step, step));
+    }
+
+    #[test]
+    fn diagonal_move_keeps_moving_at_the_smallest_step() {
+        let _time = mock_time();
+        let mut report = held(
+            MouseKeyReport::RIGHT | MouseKeyReport::UP,
+            CursorSpeed::Slow,
+        );
+
+        // a slow step of 1 would be scaled down to 0
+        report.update();
+        assert_eq!((report.x, report.y), (1, -1));
+    }
+
+    #[test]
+    fn resolution_multiplier_bits_enable_the_high_resolution_of_each_wheel() {
+        let mut report = MouseKeyReport::default();
+        let units = |report: &MouseKeyReport| {
+            (
+                report.wheel_units(MouseKeyReport::V_WHEEL_HIGH_RESOLUTION),
+                report.wheel_units(MouseKeyReport::H_WHEEL_HIGH_RESOLUTION),
+            )
+        };
+        let high = MOUSEKEY_WHEEL_RESOLUTION as u64;
+
+        assert_eq!(units(&report), (1, 1));
+
+        report.set_wheel_resolution(0x01);
+        assert_eq!(units(&report), (high, 1));
+
+        report.set_wheel_resolution(0x04);
+        assert_eq!(units(&report), (1, high));
+
+        report.set_wheel_resolution(0x0F);
+        assert_eq!(units(&report), (high, high));
+    }
+
+    #[test]
+    fn first_wheel_step_scrolls_a_whole_notch() {
+        let mut remainder = 999;
+
+        assert_eq!(
+            MouseKeyReport::wheel_step(-1, 16, &mut remainder, 8, true),
+            -16
+        );
+        assert_eq!(remainder, 0);
+    }
+
+    #[test]
+    fn wheel_step_carries_the_fractions_over() {
+        let per_step = 8 * MOUSEKEY_WHEEL_INTERVAL.as_millis();
+        let mut remainder = 0;
+        let mut scrolled = 0;
+
+        for steps in 1..=100 {
+            scrolled += MouseKeyReport::wheel_step(1, 1, &mut remainder, 8, false) as u64;
+
+            // no fraction is lost, the scrolled units and the remainder add up to the whole distance
+            assert_eq!(scrolled * 1000 + remainder, steps * per_step);
+            assert!(remainder < 1000);
+        }
+    }
+
+    #[test]
+    fn released_wheel_drops_the_remainder() {
+        let mut remainder = 500;
+
+        assert_eq!(
+            MouseKeyReport::wheel_step(0, 1, &mut remainder, 8, false),
+            0
+        );
+        assert_eq!(remainder, 0);
+    }
+
+    fn tap(warp: &mut PointerWarp, cell: u8) {
+        warp.press(cell);
+        warp.release(cell);
+    }
+
+    #[test]
+    fn every_warp_key_narrows_the_region_to_its_cell() {
+        let _time = mock_time();
+        let mut warp = PointerWarp::default();
+        let third = ABSOLUTE_POINTER_MAX / 3;
+        let ninth = third / 3;
+
+        // the center cell of the whole screen
+        tap(&mut warp, 4);
+        assert_eq!(
+            (warp.x, warp.y, warp.width, warp.height),
+            (third, third, third, third)
+        );
+        assert_eq!(warp.position(), (third + third / 2, third + third / 2));
+
+        // the top right cell of the center cell
+        tap(&mut warp, 2);
+        assert_eq!((warp.x, warp.y), (third + 2 * ninth, third));
+        assert_eq!((warp.width, warp.height), (ninth, ninth));
+    }
+
+    #[test]
+    fn held_warp_key_narrows_the_region_once() {
+        let _time = mock_time();
+        let mut warp = PointerWarp::default();
+
+        warp.press(0);
+        let region = warp;
+        warp.press(0);
+        assert_eq!((warp.width, warp.x), (region.width, region.x));
+
+        // cells out of the 3x3 grid are ignored
+        warp.press(9);
+        assert_eq!((warp.width, warp.x), (region.width, region.x));
+    }
+
+    #[test]
+    fn warp_after_the_timeout_starts_from_the_whole_screen() {
+        let _time = mock_time();
+        let mut warp = PointerWarp::default();
+
+        tap(&mut warp, 8);
+        MockDriver::get().advance(MOUSE_WARP_TIMEOUT - Duration::from_millis(1));
+        tap(&mut warp, 8);
+        assert_eq!(warp.width, ABSOLUTE_POINTER_MAX / 9);
+
+        MockDriver::get().advance(MOUSE_WARP_TIMEOUT);
+        tap(&mut warp, 8);
+        assert_eq!(warp.width, ABSOLUTE_POINTER_MAX / 3);
+    }
+
+    #[test]
+    fn warp_reset_starts_the_next_warp_from_the_whole_screen() {
+        let _time = mock_time();
+        let mut report = MouseKeyReport::default();
+
+        for action in [
+            MouseAction::Warp(0),
+            MouseAction::WarpReset,
+            MouseAction::Warp(4),
+        ] {
+            report.set_command(&action);
+            report.reset_keypress(&action);
+        }
+
+        let third = ABSOLUTE_POINTER_MAX / 3;
+        assert_eq!(
+            report.warp.position(),
+            (third + third / 2, third + third / 2)
+        );
+    }
+
+    #[test]
+    fn warped_position_is_sent_once() {
+        let _time = mock_time();
+        let mut report = MouseKeyReport::default();
+
+        report.set_command(&MouseAction::Warp(4));
+        report.update();
+        assert!(report.is_pointer_warped());
+
+        report.update();
+        assert!(!report.is_pointer_warped());
     }
 }